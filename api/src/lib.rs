@@ -23,6 +23,7 @@ pub struct Root {
     pub cargo_pkg_version: String,
     pub cargo_pkg_repository: String,
     pub git_hash: String,
+    pub peer_id: String,
 }
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct BlockHex {
@@ -56,6 +57,118 @@ pub struct StakeHex {
     pub input_address: String,
     pub hash: String,
 }
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransactionStatusHex {
+    pub status: String,
+    pub confirmations: usize,
+    pub block_hash: Option<String>,
+    pub height: Option<usize>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistoryHex {
+    pub height: usize,
+    pub hash: String,
+    pub block_hash: String,
+    pub direction: String,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Page {
+    #[serde(default)]
+    pub page: usize,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AtHeight {
+    pub height: Option<usize>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PeerStatusHex {
+    pub peer_id: String,
+    pub version: u32,
+    pub network: u8,
+    pub genesis: String,
+    pub height: usize,
+    pub hash: String,
+    pub compact_blocks: bool,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AddressBookHex {
+    pub address: String,
+    pub source: String,
+    pub last_success: u32,
+    pub last_failure: u32,
+    pub failures: u32,
+    pub banned: bool,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccessHex {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetworkTimeHex {
+    pub offset: i64,
+    pub median: Option<i64>,
+    pub samples: usize,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NatHex {
+    pub status: String,
+    pub public_address: Option<String>,
+    pub confidence: usize,
+    pub relay: bool,
+    pub reservations: Vec<String>,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrafficHex {
+    pub name: String,
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub rejected: u64,
+    pub ratelimited: u64,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyHex {
+    pub count: u64,
+    pub mean: u64,
+    pub max: u64,
+    pub failures: u64,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricsHex {
+    pub peers: Vec<TrafficHex>,
+    pub topics: Vec<TrafficHex>,
+    pub sync: TrafficHex,
+    pub latency: LatencyHex,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScoreHex {
+    pub ip_addr: String,
+    pub score: i64,
+    pub invalid: u32,
+    pub ratelimited: u32,
+    pub useful: u32,
+    pub uptime: u32,
+    pub banned_until: u32,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeaderHex {
+    pub hash: String,
+    pub previous_hash: String,
+    pub transaction_root: String,
+    pub stake_root: String,
+    pub timestamp: u32,
+    pub pi: String,
+    pub signature: String,
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProofHex {
+    pub header: HeaderHex,
+    pub height: usize,
+    pub indices: Vec<u32>,
+    pub lemmas: Vec<String>,
+}
 impl TryFrom<block::Block> for BlockHex {
     type Error = key::Error;
     fn try_from(block: block::Block) -> Result<Self, Self::Error> {
@@ -76,6 +189,19 @@ impl TryFrom<block::Block> for BlockHex {
         })
     }
 }
+impl From<block::Header> for HeaderHex {
+    fn from(header: block::Header) -> HeaderHex {
+        HeaderHex {
+            hash: hex::encode(header.hash()),
+            previous_hash: hex::encode(header.previous_hash),
+            transaction_root: hex::encode(header.transaction_root),
+            stake_root: hex::encode(header.stake_root),
+            timestamp: header.timestamp,
+            pi: hex::encode(header.pi),
+            signature: hex::encode(header.signature),
+        }
+    }
+}
 impl TryFrom<transaction::Transaction> for TransactionHex {
     type Error = key::Error;
     fn try_from(transaction: transaction::Transaction) -> Result<Self, Self::Error> {
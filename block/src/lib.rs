@@ -1,6 +1,7 @@
 use key::Error;
 use key::Key;
 use merkle_cbt::merkle_tree::Merge;
+use merkle_cbt::merkle_tree::MerkleProof;
 use merkle_cbt::CBMT as ExCBMT;
 use serde::Deserialize;
 use serde::Serialize;
@@ -8,6 +9,7 @@ use serde_big_array::BigArray;
 use sha2::Digest;
 use sha2::Sha256;
 use stake::Stake;
+use std::collections::HashMap;
 use transaction::Transaction;
 #[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Block {
@@ -66,34 +68,179 @@ impl Block {
     pub fn input_public_key(&self) -> Result<[u8; 33], Error> {
         Key::recover(&self.hash(), &self.signature)
     }
+    pub fn hash(&self) -> [u8; 32] {
+        Header::from(self).hash()
+    }
+    pub fn merkle_root(hashes: &[[u8; 32]]) -> [u8; 32] {
+        <ExCBMT<[u8; 32], Hasher>>::build_merkle_root(hashes)
+    }
+    pub fn merkle_proof(hashes: &[[u8; 32]], hash: &[u8; 32]) -> Option<Proof> {
+        let index = hashes.iter().position(|x| x == hash)?;
+        let proof = <ExCBMT<[u8; 32], Hasher>>::build_merkle_proof(hashes, &[index as u32])?;
+        Some(Proof {
+            indices: proof.indices().to_vec(),
+            lemmas: proof.lemmas().to_vec(),
+        })
+    }
+    pub fn transaction_proof(&self, hash: &[u8; 32]) -> Option<Proof> {
+        Block::merkle_proof(&self.transaction_hashes(), hash)
+    }
+    pub fn stake_proof(&self, hash: &[u8; 32]) -> Option<Proof> {
+        Block::merkle_proof(&self.stake_hashes(), hash)
+    }
+    pub fn beta(&self) -> Result<[u8; 32], Error> {
+        Key::vrf_proof_to_hash(&self.pi)
+    }
+}
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub previous_hash: [u8; 32],
+    pub transaction_root: [u8; 32],
+    pub stake_root: [u8; 32],
+    pub timestamp: u32,
+    #[serde(with = "BigArray")]
+    pub signature: [u8; 64],
+    #[serde(with = "BigArray")]
+    pub pi: [u8; 81],
+}
+impl Header {
     pub fn hash(&self) -> [u8; 32] {
         let mut array = [0; 181];
         array[0..32].copy_from_slice(&self.previous_hash);
-        array[32..64].copy_from_slice(&Block::merkle_root(&self.transaction_hashes()));
-        array[64..96].copy_from_slice(&Block::merkle_root(&self.stake_hashes()));
+        array[32..64].copy_from_slice(&self.transaction_root);
+        array[64..96].copy_from_slice(&self.stake_root);
         array[96..100].copy_from_slice(&self.timestamp.to_be_bytes());
         array[100..181].copy_from_slice(&self.pi);
         let mut hasher = Sha256::new();
         hasher.update(array);
         hasher.finalize().into()
     }
-    pub fn merkle_root(hashes: &[[u8; 32]]) -> [u8; 32] {
-        struct Hasher;
-        impl Merge for Hasher {
-            type Item = [u8; 32];
-            fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
-                let mut hasher = Sha256::new();
-                hasher.update(left);
-                hasher.update(right);
-                hasher.finalize().into()
-            }
-        }
-        <ExCBMT<[u8; 32], Hasher>>::build_merkle_root(hashes)
+    pub fn input_public_key(&self) -> Result<[u8; 33], Error> {
+        Key::recover(&self.hash(), &self.signature)
+    }
+    pub fn input_address(&self) -> Result<[u8; 20], Error> {
+        Ok(Key::address(&self.input_public_key()?))
     }
     pub fn beta(&self) -> Result<[u8; 32], Error> {
         Key::vrf_proof_to_hash(&self.pi)
     }
 }
+impl From<&Block> for Header {
+    fn from(block: &Block) -> Header {
+        Header {
+            previous_hash: block.previous_hash,
+            transaction_root: Block::merkle_root(&block.transaction_hashes()),
+            stake_root: Block::merkle_root(&block.stake_hashes()),
+            timestamp: block.timestamp,
+            signature: block.signature,
+            pi: block.pi,
+        }
+    }
+}
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CompactBlock {
+    pub header: Header,
+    pub transactions: Vec<[u8; 8]>,
+    pub stakes: Vec<[u8; 8]>,
+}
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Missing {
+    pub transactions: Vec<u32>,
+    pub stakes: Vec<u32>,
+}
+impl CompactBlock {
+    pub fn hash(&self) -> [u8; 32] {
+        self.header.hash()
+    }
+    pub fn rebuild(
+        &self,
+        transactions: &[Transaction],
+        stakes: &[Stake],
+    ) -> Result<Block, Missing> {
+        let mut missing = Missing::default();
+        let mut block = Block {
+            previous_hash: self.header.previous_hash,
+            timestamp: self.header.timestamp,
+            signature: self.header.signature,
+            pi: self.header.pi,
+            transactions: vec![],
+            stakes: vec![],
+        };
+        let transactions: HashMap<[u8; 8], &Transaction> = transactions
+            .iter()
+            .map(|a| (CompactBlock::short_id(&a.hash()), a))
+            .collect();
+        let stakes: HashMap<[u8; 8], &Stake> = stakes
+            .iter()
+            .map(|a| (CompactBlock::short_id(&a.hash()), a))
+            .collect();
+        for (i, short_id) in self.transactions.iter().enumerate() {
+            match transactions.get(short_id) {
+                Some(transaction) => block.transactions.push(**transaction),
+                None => missing.transactions.push(i as u32),
+            }
+        }
+        for (i, short_id) in self.stakes.iter().enumerate() {
+            match stakes.get(short_id) {
+                Some(stake) => block.stakes.push(**stake),
+                None => missing.stakes.push(i as u32),
+            }
+        }
+        if missing != Missing::default() {
+            return Err(missing);
+        }
+        if Header::from(&block) != self.header {
+            return Err(Missing {
+                transactions: (0..self.transactions.len() as u32).collect(),
+                stakes: (0..self.stakes.len() as u32).collect(),
+            });
+        }
+        Ok(block)
+    }
+    pub fn short_id(hash: &[u8; 32]) -> [u8; 8] {
+        let mut short_id = [0; 8];
+        short_id.copy_from_slice(&hash[..8]);
+        short_id
+    }
+}
+impl From<&Block> for CompactBlock {
+    fn from(block: &Block) -> CompactBlock {
+        CompactBlock {
+            header: Header::from(block),
+            transactions: block
+                .transaction_hashes()
+                .iter()
+                .map(CompactBlock::short_id)
+                .collect(),
+            stakes: block
+                .stake_hashes()
+                .iter()
+                .map(CompactBlock::short_id)
+                .collect(),
+        }
+    }
+}
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Proof {
+    pub indices: Vec<u32>,
+    pub lemmas: Vec<[u8; 32]>,
+}
+impl Proof {
+    pub fn verify(&self, root: &[u8; 32], hash: &[u8; 32]) -> bool {
+        <MerkleProof<[u8; 32], Hasher>>::new(self.indices.clone(), self.lemmas.clone())
+            .verify(root, &[*hash])
+    }
+}
+struct Hasher;
+impl Merge for Hasher {
+    type Item = [u8; 32];
+    fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+        let mut hasher = Sha256::new();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+}
 impl Default for Block {
     fn default() -> Block {
         Block {
@@ -120,6 +267,43 @@ mod tests {
         );
     }
     #[test]
+    fn merkle_proof() {
+        let hashes: Vec<[u8; 32]> = (0..5).map(|i| [i; 32]).collect();
+        let root = Block::merkle_root(&hashes);
+        for hash in hashes.iter() {
+            let proof = Block::merkle_proof(&hashes, hash).unwrap();
+            assert!(proof.verify(&root, hash));
+            assert!(!proof.verify(&root, &[0xff; 32]));
+        }
+        assert_eq!(Block::merkle_proof(&hashes, &[0xff; 32]), None);
+    }
+    #[test]
+    fn compact_block() {
+        let transaction = Transaction {
+            timestamp: 1,
+            ..Default::default()
+        };
+        let stake = Stake {
+            timestamp: 1,
+            ..Default::default()
+        };
+        let block = Block {
+            transactions: vec![transaction],
+            stakes: vec![stake],
+            ..Default::default()
+        };
+        let compact_block = CompactBlock::from(&block);
+        assert_eq!(compact_block.hash(), block.hash());
+        assert_eq!(
+            compact_block.rebuild(&[], &[stake]),
+            Err(Missing {
+                transactions: vec![0],
+                stakes: vec![],
+            })
+        );
+        assert_eq!(compact_block.rebuild(&[transaction], &[stake]), Ok(block));
+    }
+    #[test]
     fn bincode_serialize() {
         assert_eq!(bincode::serialize(&Block::default()).unwrap().len(), 197);
     }
@@ -1,6 +1,13 @@
 use block::Block;
-use chrono::Utc;
+use block::CompactBlock;
+use block::Header;
+use block::Missing;
+use block::Proof;
+use clock::Clock;
+use clock::SharedClock;
 use colored::*;
+use db::archive::ArchiveDB;
+use db::history::HistoryDB;
 use fork::Manager;
 use fork::Stable;
 use fork::Unstable;
@@ -11,8 +18,12 @@ use rocksdb::DB;
 use serde::Deserialize;
 use serde::Serialize;
 use stake::Stake;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
 use sync::Sync;
 use tracing::debug;
+use tracing::error;
 use tracing::info;
 use tracing::instrument;
 use tracing::warn;
@@ -21,6 +32,10 @@ use tree::Tree;
 use tree::GENESIS_BLOCK_PREVIOUS_HASH;
 pub const BLOCK_SIZE_LIMIT: usize = 57797;
 pub const GENESIS_BLOCK_BETA: [u8; 32] = [0; 32];
+pub const ORPHANS_MAX_LEN: usize = 64;
+pub const PENDING_HEADERS_MAX_LEN: usize = 10_000;
+pub const COMPACT_BLOCKS_MAX_LEN: usize = 16;
+pub const EXPIRED_MAX_LEN: usize = 10_000;
 lazy_static! {
     pub static ref EMPTY_BLOCK_SIZE: usize = bincode::serialize(&Block::default()).unwrap().len();
     pub static ref TRANSACTION_SIZE: usize =
@@ -38,6 +53,9 @@ pub enum Error {
     BlockTimestampFuture,
     BlockTimestamp,
     BlockStakerAddress,
+    HeaderPreviousHash,
+    HeaderPending,
+    HeaderMismatch,
     TransactionPending,
     TransactionTooExpensive,
     TransactionAmountZero,
@@ -55,9 +73,57 @@ pub enum Error {
     StakeTimestampFuture,
     StakeTimestamp,
     StakeInChain,
+    Height,
     HeightByHash,
     HashByHeight,
     SyncBlock,
+    Archive,
+    Proof,
+    CompactBlockMissing(Missing),
+}
+impl Error {
+    pub fn invalid(&self) -> bool {
+        matches!(
+            self,
+            Error::Key(_)
+                | Error::Fork(fork::Error::Overflow)
+                | Error::BlockTimestamp
+                | Error::BlockStakerAddress
+                | Error::HeaderMismatch
+                | Error::TransactionAmountZero
+                | Error::TransactionFeeZero
+                | Error::TransactionInputOutput
+                | Error::StakeAmountZero
+                | Error::StakeFeeZero
+        )
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Status {
+    Unknown,
+    Pending,
+    Unstable,
+    Stable,
+    Expired,
+}
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Status::Unknown => "unknown",
+            Status::Pending => "pending",
+            Status::Unstable => "unstable",
+            Status::Stable => "stable",
+            Status::Expired => "expired",
+        };
+        write!(f, "{}", str)
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionStatus {
+    pub status: Status,
+    pub confirmations: usize,
+    pub block_hash: Option<[u8; 32]>,
+    pub height: Option<usize>,
 }
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Blockchain {
@@ -67,10 +133,28 @@ pub struct Blockchain {
     pending_transactions: Vec<Transaction>,
     pending_stakes: Vec<Stake>,
     pending_blocks: Vec<Block>,
+    pending_headers: Vec<Header>,
+    pending_bodies: HashMap<[u8; 32], Block>,
+    orphans: Vec<Block>,
+    compact_blocks: HashMap<[u8; 32], (CompactBlock, u32)>,
+    expired: VecDeque<[u8; 32]>,
+    #[serde(skip)]
+    pub clock: SharedClock,
 }
 impl Blockchain {
+    pub fn new(clock: SharedClock) -> Blockchain {
+        Blockchain {
+            clock,
+            ..Default::default()
+        }
+    }
     #[instrument(skip_all, level = "debug")]
-    pub fn load(&mut self, db: &DB, trust_fork_after_blocks: usize) -> Result<(), Error> {
+    pub fn load(
+        &mut self,
+        db: &DB,
+        trust_fork_after_blocks: usize,
+        archive: bool,
+    ) -> Result<(), Error> {
         db::tree::reload(db, &mut self.tree).map_err(Error::DB)?;
         let (mut stable_hashes, unstable_hashes) = self
             .tree
@@ -83,15 +167,22 @@ impl Blockchain {
             unstable_hashes = unstable_hashes.len(),
             tree_size = self.tree.size(),
         );
-        if let Ok(checkpoint) = db::checkpoint::get(db) {
+        if archive {
+            info!("archive mode, replaying stable chain from genesis");
+        } else if let Ok(checkpoint) = db::checkpoint::get(db) {
             info!(height = checkpoint.height, "using checkpoint at");
             self.forks.stable = Stable::from_checkpoint(
                 stable_hashes.drain(..checkpoint.height).collect(),
                 checkpoint,
             );
         }
-        self.forks.stable.load(db, &stable_hashes);
+        self.forks.stable.archive = archive;
+        self.forks
+            .stable
+            .load(db, &stable_hashes)
+            .map_err(Error::Fork)?;
         self.forks.unstable = Unstable::from(db, &unstable_hashes, &self.forks.stable);
+        self.forks.rollback(db).map_err(Error::DB)?;
         Ok(())
     }
     pub fn last_seen(&self) -> String {
@@ -99,7 +190,7 @@ impl Blockchain {
             return "never".to_string();
         }
         let timestamp = self.forks.unstable.latest_block.timestamp;
-        let diff = (Utc::now().timestamp() as u32).saturating_sub(timestamp);
+        let diff = self.clock.timestamp().saturating_sub(timestamp);
         let now = "just now";
         let mut string = duration_to_string(diff, now);
         if string != now {
@@ -134,7 +225,104 @@ impl Blockchain {
             Ok(hash)
         }
     }
+    pub fn transaction_status(&self, db: &DB, hash: &[u8; 32]) -> Result<TransactionStatus, Error> {
+        for block_hash in db::transaction_block::get(db, hash).map_err(Error::DB)? {
+            let height = match self.height_by_hash(&block_hash) {
+                Ok(height) => height,
+                Err(_) => continue,
+            };
+            let status = if height > self.forks.stable.hashes.len() {
+                Status::Unstable
+            } else {
+                Status::Stable
+            };
+            return Ok(TransactionStatus {
+                status,
+                confirmations: self.height() - height + 1,
+                block_hash: Some(block_hash),
+                height: Some(height),
+            });
+        }
+        let status = if self.pending_transactions.iter().any(|a| &a.hash() == hash) {
+            Status::Pending
+        } else if self.expired.contains(hash) {
+            Status::Expired
+        } else {
+            match db::transaction::get(db, hash) {
+                Ok(transaction)
+                    if fork::elapsed(
+                        transaction.timestamp,
+                        self.forks.unstable.latest_block.timestamp,
+                    ) =>
+                {
+                    Status::Expired
+                }
+                Ok(_) | Err(db::Error::NotFound) => Status::Unknown,
+                Err(e) => return Err(Error::DB(e)),
+            }
+        };
+        Ok(TransactionStatus {
+            status,
+            confirmations: 0,
+            block_hash: None,
+            height: None,
+        })
+    }
+    pub fn transaction_proof(
+        &self,
+        db: &DB,
+        hash: &[u8; 32],
+    ) -> Result<(Header, usize, Proof), Error> {
+        let transaction_status = self.transaction_status(db, hash)?;
+        let (block_hash, height) = match transaction_status {
+            TransactionStatus {
+                block_hash: Some(block_hash),
+                height: Some(height),
+                ..
+            } => (block_hash, height),
+            _ => return Err(Error::Proof),
+        };
+        let block = db::block::get(db, &block_hash).map_err(Error::DB)?;
+        let proof = block.transaction_proof(hash).ok_or(Error::Proof)?;
+        Ok((Header::from(&block), height, proof))
+    }
+    pub fn history(
+        &self,
+        db: &DB,
+        address: &[u8; 20],
+        skip: usize,
+        take: usize,
+    ) -> Result<Vec<HistoryDB>, Error> {
+        let stable_height = self.forks.stable.hashes.len();
+        let mut unstable = vec![];
+        for (index, hash) in self.forks.unstable.hashes.iter().enumerate().rev() {
+            let block = db::block::get(db, hash).map_err(Error::DB)?;
+            for (a, history) in HistoryDB::from_block(&block, stable_height + index + 1) {
+                if &a == address {
+                    unstable.push(history);
+                }
+            }
+        }
+        let stable_skip = skip.saturating_sub(unstable.len());
+        let mut vec: Vec<HistoryDB> = unstable.into_iter().skip(skip).take(take).collect();
+        let take = take - vec.len();
+        if take == 0 {
+            return Ok(vec);
+        }
+        vec.extend(
+            db::history::get(db, address, stable_height, stable_skip, take).map_err(Error::DB)?,
+        );
+        Ok(vec)
+    }
     pub fn sync_block(&mut self, db: &DB, index: usize) -> Result<Block, Error> {
+        let hash = self.sync_hash(index)?;
+        db::block::get(db, &hash).map_err(Error::DB)
+    }
+    pub fn sync_header(&mut self, db: &DB, index: usize) -> Result<Header, Error> {
+        let hash = self.sync_hash(index)?;
+        db::block::get_header(db, &hash).map_err(Error::DB)
+    }
+    fn sync_hash(&self, index: usize) -> Result<[u8; 32], Error> {
         if index >= self.height() {
             return Err(Error::SyncBlock);
         }
@@ -143,7 +331,7 @@ impl Blockchain {
         } else {
             self.forks.unstable.hashes[index - self.forks.stable.hashes.len()]
         };
-        db::block::get(db, &hash).map_err(Error::DB)
+        Ok(hash)
     }
     pub fn forge_block(
         &mut self,
@@ -222,11 +410,13 @@ impl Blockchain {
                 self.sync.new += 1.0;
             }
         }
-        self.forks.update(
+        if let Err(e) = self.forks.update(
             db,
             &self.tree.unstable_hashes(trust_fork_after_blocks),
             trust_fork_after_blocks,
-        );
+        ) {
+            error!(?e);
+        }
         let height = self.height();
         let hash = hex::encode(block.hash());
         let transactions = block.transactions.len();
@@ -239,7 +429,7 @@ impl Blockchain {
         info!(height, fork, hash, transactions, stakes, "{}", text);
     }
     pub fn save_blocks(&mut self, db: &DB, trust_fork_after_blocks: usize) {
-        let timestamp = Utc::now().timestamp() as u32;
+        let timestamp = self.clock.timestamp();
         let mut vec = vec![];
         let mut i = 0;
         while i < self.pending_blocks.len() {
@@ -275,7 +465,7 @@ impl Blockchain {
         Blockchain::validate_transaction(
             &self.forks.unstable,
             &transaction,
-            Utc::now().timestamp() as u32 + time_delta,
+            self.clock.timestamp() + time_delta,
         )?;
         let hash = hex::encode(transaction.hash());
         info!(hash, "Transaction");
@@ -307,7 +497,7 @@ impl Blockchain {
         Blockchain::validate_stake(
             &self.forks.unstable,
             &stake,
-            Utc::now().timestamp() as u32 + time_delta,
+            self.clock.timestamp() + time_delta,
         )?;
         let hash = hex::encode(stake.hash());
         info!(hash, "Stake");
@@ -327,17 +517,216 @@ impl Blockchain {
         self.validate_block(
             db,
             &block,
-            Utc::now().timestamp() as u32 + time_delta,
+            self.clock.timestamp() + time_delta,
             trust_fork_after_blocks,
         )?;
         self.pending_blocks.push(block);
         Ok(())
     }
+    pub fn pending_headers_push(
+        &mut self,
+        db: &DB,
+        headers: Vec<Header>,
+        time_delta: u32,
+    ) -> Result<(), Error> {
+        let timestamp = self.clock.timestamp() + time_delta;
+        let mut previous = self.pending_headers.last().cloned();
+        for header in headers {
+            let hash = header.hash();
+            if self.tree.get(&hash).is_some() {
+                previous = Some(header);
+                continue;
+            }
+            if self.pending_headers.iter().any(|a| a.hash() == hash) {
+                return Err(Error::HeaderPending);
+            }
+            if self.pending_headers.len() >= PENDING_HEADERS_MAX_LEN {
+                break;
+            }
+            let previous_header = match previous {
+                Some(previous) => {
+                    if header.previous_hash != previous.hash() {
+                        return Err(Error::HeaderPreviousHash);
+                    }
+                    previous
+                }
+                None => {
+                    if header.previous_hash == GENESIS_BLOCK_PREVIOUS_HASH {
+                        Header::from(&Block::default())
+                    } else if self.tree.get(&header.previous_hash).is_some() {
+                        db::block::get_header(db, &header.previous_hash).map_err(Error::DB)?
+                    } else {
+                        return Err(Error::HeaderPreviousHash);
+                    }
+                }
+            };
+            Blockchain::validate_header(&header, &previous_header, timestamp)?;
+            self.pending_headers.push(header.clone());
+            previous = Some(header);
+        }
+        Ok(())
+    }
+    pub fn pending_headers_missing(&self) -> Vec<[u8; 32]> {
+        self.pending_headers
+            .iter()
+            .map(|a| a.hash())
+            .filter(|a| !self.pending_bodies.contains_key(a))
+            .collect()
+    }
+    pub fn pending_bodies_push(&mut self, block: Block) -> Result<(), Error> {
+        let hash = block.hash();
+        let header = self
+            .pending_headers
+            .iter()
+            .find(|a| a.hash() == hash)
+            .ok_or(Error::HeaderMismatch)?;
+        if header != &Header::from(&block) {
+            return Err(Error::HeaderMismatch);
+        }
+        self.pending_bodies.insert(hash, block);
+        Ok(())
+    }
+    pub fn pending_bodies_take(&mut self) -> Vec<Block> {
+        let mut vec = vec![];
+        while let Some(header) = self.pending_headers.first() {
+            match self.pending_bodies.remove(&header.hash()) {
+                Some(block) => {
+                    self.pending_headers.remove(0);
+                    vec.push(block);
+                }
+                None => break,
+            }
+        }
+        vec
+    }
+    pub fn pending_headers_is_empty(&self) -> bool {
+        self.pending_headers.is_empty()
+    }
+    pub fn pending_headers_clear(&mut self) {
+        self.pending_headers.clear();
+        self.pending_bodies.clear();
+    }
+    pub fn orphans_push(&mut self, block: Block, time_delta: u32) -> Result<bool, Error> {
+        block.input_public_key().map_err(Error::Key)?;
+        if block.timestamp > self.clock.timestamp() + time_delta {
+            return Err(Error::BlockTimestampFuture);
+        }
+        let timestamp = self.forks.stable.latest_block.timestamp;
+        self.orphans
+            .retain(|a| a.timestamp > timestamp && self.tree.get(&a.hash()).is_none());
+        let hash = block.hash();
+        if self.orphans.iter().any(|a| a.hash() == hash) || self.tree.get(&hash).is_some() {
+            return Ok(false);
+        }
+        if self.orphans.len() >= ORPHANS_MAX_LEN {
+            self.orphans.remove(0);
+        }
+        debug!(hash = hex::encode(hash), "Orphan");
+        self.orphans.push(block);
+        Ok(true)
+    }
+    pub fn orphans_contains(&self, hash: &[u8; 32]) -> bool {
+        self.orphans.iter().any(|a| &a.hash() == hash)
+    }
+    pub fn orphans_take(&mut self) -> Vec<Block> {
+        let mut vec = vec![];
+        let mut i = 0;
+        while i < self.orphans.len() {
+            if self.tree.get(&self.orphans[i].previous_hash).is_some() {
+                vec.push(self.orphans.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        vec
+    }
+    pub fn orphans_connect(&mut self, db: &DB, time_delta: u32, trust_fork_after_blocks: usize) {
+        loop {
+            let vec = self.orphans_take();
+            if vec.is_empty() {
+                break;
+            }
+            for block in vec {
+                if let Err(e) =
+                    self.pending_blocks_push(db, block, time_delta, trust_fork_after_blocks)
+                {
+                    debug!(?e, "Orphan");
+                }
+            }
+            self.save_blocks(db, trust_fork_after_blocks);
+        }
+    }
+    pub fn pending_block(&self, hash: &[u8; 32]) -> Option<&Block> {
+        self.pending_blocks.iter().find(|a| &a.hash() == hash)
+    }
+    pub fn compact_blocks_push(
+        &mut self,
+        db: &DB,
+        compact_block: CompactBlock,
+        time_delta: u32,
+    ) -> Result<Block, Error> {
+        let header = &compact_block.header;
+        let previous_header = if header.previous_hash == GENESIS_BLOCK_PREVIOUS_HASH {
+            Header::from(&Block::default())
+        } else if self.tree.get(&header.previous_hash).is_some() {
+            db::block::get_header(db, &header.previous_hash).map_err(Error::DB)?
+        } else {
+            return Err(Error::BlockPreviousHashNotInTree);
+        };
+        let timestamp = self.clock.timestamp();
+        Blockchain::validate_header(header, &previous_header, timestamp + time_delta)?;
+        let missing = match compact_block.rebuild(&self.pending_transactions, &self.pending_stakes)
+        {
+            Ok(block) => return Ok(block),
+            Err(missing) => missing,
+        };
+        if self.compact_blocks.len() >= COMPACT_BLOCKS_MAX_LEN {
+            if let Some(hash) = self
+                .compact_blocks
+                .iter()
+                .min_by_key(|(_, (_, inserted))| *inserted)
+                .map(|(hash, _)| *hash)
+            {
+                self.compact_blocks.remove(&hash);
+            }
+        }
+        self.compact_blocks
+            .insert(compact_block.hash(), (compact_block, timestamp));
+        Err(Error::CompactBlockMissing(missing))
+    }
+    pub fn compact_blocks_fill(
+        &mut self,
+        hash: &[u8; 32],
+        transactions: Vec<Transaction>,
+        stakes: Vec<Stake>,
+    ) -> Option<Block> {
+        let (compact_block, _) = self.compact_blocks.remove(hash)?;
+        let transactions = [transactions, self.pending_transactions.clone()].concat();
+        let stakes = [stakes, self.pending_stakes.clone()].concat();
+        compact_block.rebuild(&transactions, &stakes).ok()
+    }
+    pub fn pending_transactions(&self) -> &[Transaction] {
+        &self.pending_transactions
+    }
+    pub fn pending_stakes(&self) -> &[Stake] {
+        &self.pending_stakes
+    }
     pub fn pending_retain(&mut self, timestamp: u32) {
-        self.pending_transactions
-            .retain(|a| !fork::elapsed(a.timestamp, timestamp));
+        let expired = &mut self.expired;
+        self.pending_transactions.retain(|a| {
+            if !fork::elapsed(a.timestamp, timestamp) {
+                return true;
+            }
+            expired.push_back(a.hash());
+            false
+        });
+        while self.expired.len() > EXPIRED_MAX_LEN {
+            self.expired.pop_front();
+        }
         self.pending_stakes
             .retain(|a| !fork::elapsed(a.timestamp, timestamp));
+        self.compact_blocks
+            .retain(|_, (_, inserted)| timestamp.saturating_sub(*inserted) < BLOCK_TIME);
     }
     fn validate_transaction(
         unstable: &Unstable,
@@ -382,6 +771,25 @@ impl Blockchain {
         }
         Ok(())
     }
+    fn validate_header(
+        header: &Header,
+        previous_header: &Header,
+        timestamp: u32,
+    ) -> Result<(), Error> {
+        if header.timestamp > timestamp {
+            return Err(Error::BlockTimestampFuture);
+        }
+        if !validate_block_timestamp(header.timestamp, previous_header.timestamp) {
+            return Err(Error::BlockTimestamp);
+        }
+        Key::vrf_verify(
+            &header.input_public_key().map_err(Error::Key)?,
+            &header.pi,
+            &previous_header.beta().map_err(Error::Key)?,
+        )
+        .map_err(Error::Key)?;
+        Ok(())
+    }
     pub fn validate_block(
         &self,
         db: &DB,
@@ -438,6 +846,36 @@ impl Blockchain {
     pub fn balance(&self, address: &[u8; 20]) -> u128 {
         self.forks.unstable.balance(address)
     }
+    pub fn balance_at(&self, db: &DB, address: &[u8; 20], height: usize) -> Result<u128, Error> {
+        Ok(self.archive_at(db, address, height)?.balance)
+    }
+    pub fn staked_at(&self, db: &DB, address: &[u8; 20], height: usize) -> Result<u128, Error> {
+        Ok(self.archive_at(db, address, height)?.staked)
+    }
+    fn archive_at(&self, db: &DB, address: &[u8; 20], height: usize) -> Result<ArchiveDB, Error> {
+        if height > self.height() {
+            return Err(Error::Height);
+        }
+        if height == self.height() {
+            return Ok(ArchiveDB {
+                balance: self.balance(address),
+                staked: self.staked(address),
+            });
+        }
+        let stable_height = self.forks.stable.hashes.len();
+        if height > stable_height {
+            let hashes = &self.forks.unstable.hashes[..height - stable_height];
+            let unstable = Unstable::from(db, hashes, &self.forks.stable);
+            return Ok(ArchiveDB {
+                balance: unstable.balance(address),
+                staked: unstable.staked(address),
+            });
+        }
+        if !self.forks.stable.archive {
+            return Err(Error::Archive);
+        }
+        db::archive::get(db, address, height).map_err(Error::DB)
+    }
     pub fn balance_pending_min(&self, address: &[u8; 20]) -> u128 {
         let mut balance = self.balance(address);
         for transaction in self.pending_transactions.iter() {
@@ -532,6 +970,8 @@ pub fn duration_to_string(seconds: u32, now: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use clock::ManualClock;
+    use tempdir::TempDir;
     #[test]
     fn test_block_size_limit() {
         assert_eq!(
@@ -539,4 +979,266 @@ mod tests {
             *EMPTY_BLOCK_SIZE + *TRANSACTION_SIZE * 600
         );
     }
+    #[test]
+    fn test_orphans() {
+        let manual = ManualClock::new(60_000_000_000);
+        let mut blockchain = Blockchain::new(SharedClock::new(manual.clone()));
+        let key = Key::generate();
+        let timestamp = blockchain.clock.timestamp();
+        let orphan = |previous_hash, timestamp| {
+            Block::sign(previous_hash, timestamp, vec![], vec![], &key, &[0; 32]).unwrap()
+        };
+        assert!(matches!(
+            blockchain.orphans_push(
+                Block {
+                    previous_hash: [1; 32],
+                    timestamp,
+                    ..Default::default()
+                },
+                0
+            ),
+            Err(Error::Key(_))
+        ));
+        assert!(matches!(
+            blockchain.orphans_push(orphan([1; 32], timestamp + 60), 0),
+            Err(Error::BlockTimestampFuture)
+        ));
+        let block = orphan([1; 32], timestamp);
+        assert!(blockchain.orphans_push(block.clone(), 0).unwrap());
+        assert!(!blockchain.orphans_push(block.clone(), 0).unwrap());
+        assert!(blockchain.orphans_take().is_empty());
+        blockchain.tree.insert([1; 32], [0; 32], 0);
+        assert_eq!(blockchain.orphans_take(), vec![block]);
+        let blocks: Vec<Block> = (0..ORPHANS_MAX_LEN as u8 + 1)
+            .map(|i| orphan([i + 2; 32], timestamp))
+            .collect();
+        for block in blocks.iter() {
+            assert!(blockchain.orphans_push(block.clone(), 0).unwrap());
+        }
+        assert_eq!(blockchain.orphans.len(), ORPHANS_MAX_LEN);
+        assert!(!blockchain.orphans_contains(&blocks[0].hash()));
+    }
+    #[test]
+    fn test_last_seen() {
+        let manual = ManualClock::new(0);
+        let mut blockchain = Blockchain::new(SharedClock::new(manual.clone()));
+        assert_eq!(blockchain.last_seen(), "never");
+        blockchain.forks.unstable.latest_block.timestamp = 1_000_000;
+        manual.set(1_000_000_000);
+        assert_eq!(blockchain.last_seen(), "just now");
+        manual.advance(2 * 3_600_000 + 60_000);
+        assert_eq!(blockchain.last_seen(), "2 hours and 1 minute ago");
+    }
+    #[test]
+    fn test_forge_and_validate() {
+        let trust = 10;
+        let manual = ManualClock::new(60_000_000_000);
+        let forger_tempdir = TempDir::new("tofuri-forger").unwrap();
+        let forger_db = db::open_cf_descriptors(forger_tempdir.path());
+        let validator_tempdir = TempDir::new("tofuri-validator").unwrap();
+        let validator_db = db::open_cf_descriptors(validator_tempdir.path());
+        let mut forger = Blockchain::new(SharedClock::new(manual.clone()));
+        let mut validator = Blockchain::new(SharedClock::new(manual.clone()));
+        let key = Key::generate();
+        let genesis = forger.forge_block(&forger_db, &key, forger.clock.timestamp(), trust);
+        validator.save_block(&validator_db, &genesis, false, trust);
+        for _ in 0..3 * 60 {
+            let timestamp = manual.timestamp() + BLOCK_TIME;
+            let block = forger.forge_block(&forger_db, &key, timestamp, trust);
+            assert!(matches!(
+                validator.pending_blocks_push(&validator_db, block.clone(), 0, trust),
+                Err(Error::BlockTimestampFuture)
+            ));
+            manual.advance(BLOCK_TIME as i64 * 1_000);
+            validator
+                .pending_blocks_push(&validator_db, block.clone(), 0, trust)
+                .unwrap();
+            validator.save_blocks(&validator_db, trust);
+            assert_eq!(validator.height(), forger.height());
+            assert_eq!(validator.tree.main().unwrap().hash, block.hash());
+        }
+        assert_eq!(validator.height(), 3 * 60 + 1);
+        assert_eq!(
+            validator.forks.stable.hashes.len(),
+            forger.forks.stable.hashes.len()
+        );
+    }
+    #[test]
+    fn test_history_index() {
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let manual = ManualClock::new(60_000_000_000);
+        let mut blockchain = Blockchain::new(SharedClock::new(manual.clone()));
+        let key = Key::generate();
+        for _ in 0..20 {
+            manual.advance(BLOCK_TIME as i64 * 1_000);
+            let timestamp = blockchain.clock.timestamp();
+            blockchain.forge_block(&db, &key, timestamp, 10);
+        }
+        let stable = blockchain.forks.stable.hashes.len();
+        assert!(stable > 0);
+        assert_eq!(db::history::height(&db).unwrap(), stable);
+        db::history::put_height(&db, 0).unwrap();
+        let mut blockchain = Blockchain::default();
+        blockchain.load(&db, 5, false).unwrap();
+        assert!(blockchain.forks.stable.hashes.len() > stable);
+        assert_eq!(
+            db::history::height(&db).unwrap(),
+            blockchain.forks.stable.hashes.len()
+        );
+        let mut blockchain = Blockchain::default();
+        blockchain.load(&db, 10, false).unwrap();
+        assert_eq!(blockchain.forks.stable.hashes.len(), stable);
+        assert_eq!(db::history::height(&db).unwrap(), stable);
+    }
+    #[test]
+    fn test_transaction_status() {
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let manual = ManualClock::new(60_000_000_000);
+        let mut blockchain = Blockchain::new(SharedClock::new(manual.clone()));
+        let key = Key::generate();
+        let trust = 3;
+        let forge = |blockchain: &mut Blockchain| {
+            manual.advance(BLOCK_TIME as i64 * 1_000);
+            let timestamp = blockchain.clock.timestamp();
+            blockchain.forge_block(&db, &key, timestamp, trust);
+        };
+        let status = |blockchain: &Blockchain, transaction: &Transaction| {
+            blockchain
+                .transaction_status(&db, &transaction.hash())
+                .unwrap()
+                .status
+        };
+        forge(&mut blockchain);
+        let transaction =
+            Transaction::sign([1; 20], 1, 1, blockchain.clock.timestamp(), &key).unwrap();
+        assert_eq!(status(&blockchain, &transaction), Status::Unknown);
+        blockchain
+            .pending_transactions_push(transaction.clone(), 0)
+            .unwrap();
+        assert_eq!(status(&blockchain, &transaction), Status::Pending);
+        forge(&mut blockchain);
+        assert_eq!(status(&blockchain, &transaction), Status::Unstable);
+        for _ in 0..=trust {
+            forge(&mut blockchain);
+        }
+        assert_eq!(status(&blockchain, &transaction), Status::Stable);
+        let expired = Transaction::sign([1; 20], 2, 1, blockchain.clock.timestamp(), &key).unwrap();
+        blockchain
+            .pending_transactions_push(expired.clone(), 0)
+            .unwrap();
+        manual.advance((fork::ELAPSED + BLOCK_TIME) as i64 * 1_000);
+        blockchain.pending_retain(blockchain.clock.timestamp());
+        assert_eq!(status(&blockchain, &expired), Status::Expired);
+    }
+    #[test]
+    fn test_pending_headers_stalled() {
+        let trust = 10;
+        let manual = ManualClock::new(60_000_000_000);
+        let forger_tempdir = TempDir::new("tofuri-forger").unwrap();
+        let forger_db = db::open_cf_descriptors(forger_tempdir.path());
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let mut forger = Blockchain::new(SharedClock::new(manual.clone()));
+        let mut blockchain = Blockchain::new(SharedClock::new(manual.clone()));
+        let key = Key::generate();
+        let mut blocks = vec![];
+        for _ in 0..5 {
+            manual.advance(BLOCK_TIME as i64 * 1_000);
+            let timestamp = forger.clock.timestamp();
+            blocks.push(forger.forge_block(&forger_db, &key, timestamp, trust));
+        }
+        blockchain.save_block(&db, &blocks[0], false, trust);
+        let headers: Vec<Header> = blocks[1..].iter().map(Header::from).collect();
+        blockchain
+            .pending_headers_push(&db, headers.clone(), 0)
+            .unwrap();
+        assert_eq!(blockchain.pending_headers_missing().len(), 4);
+        blockchain.pending_bodies_push(blocks[2].clone()).unwrap();
+        assert!(blockchain.pending_bodies_take().is_empty());
+        assert_eq!(blockchain.pending_headers_missing().len(), 3);
+        blockchain.pending_headers_clear();
+        assert!(blockchain.pending_headers_is_empty());
+        assert!(blockchain.pending_headers_missing().is_empty());
+        blockchain.pending_headers_push(&db, headers, 0).unwrap();
+        for block in blocks[1..].iter() {
+            blockchain.pending_bodies_push(block.clone()).unwrap();
+        }
+        assert_eq!(blockchain.pending_bodies_take(), blocks[1..].to_vec());
+    }
+    #[test]
+    fn test_compact_blocks() {
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let manual = ManualClock::new(60_000_000_000);
+        let mut blockchain = Blockchain::new(SharedClock::new(manual.clone()));
+        let key = Key::generate();
+        let genesis = blockchain.forge_block(&db, &key, blockchain.clock.timestamp(), 10);
+        let beta = genesis.beta().unwrap();
+        let compact_block = |timestamp, amount| {
+            let transaction = Transaction::sign([1; 20], amount, 1, timestamp, &key).unwrap();
+            let block = Block::sign(
+                genesis.hash(),
+                timestamp,
+                vec![transaction],
+                vec![],
+                &key,
+                &beta,
+            )
+            .unwrap();
+            CompactBlock::from(&block)
+        };
+        manual.advance(BLOCK_TIME as i64 * 1_000);
+        let timestamp = blockchain.clock.timestamp();
+        assert!(matches!(
+            blockchain.compact_blocks_push(&db, compact_block(timestamp + 3600, 1), 0),
+            Err(Error::BlockTimestampFuture)
+        ));
+        assert!(blockchain.compact_blocks.is_empty());
+        let compact_blocks: Vec<CompactBlock> = (1..=COMPACT_BLOCKS_MAX_LEN as u128 + 1)
+            .map(|amount| compact_block(timestamp, amount))
+            .collect();
+        for compact_block in compact_blocks.iter() {
+            assert!(matches!(
+                blockchain.compact_blocks_push(&db, compact_block.clone(), 0),
+                Err(Error::CompactBlockMissing(_))
+            ));
+            manual.advance(1_000);
+        }
+        assert_eq!(blockchain.compact_blocks.len(), COMPACT_BLOCKS_MAX_LEN);
+        assert!(!blockchain
+            .compact_blocks
+            .contains_key(&compact_blocks[0].hash()));
+        manual.advance(BLOCK_TIME as i64 * 1_000);
+        blockchain.pending_retain(blockchain.clock.timestamp());
+        assert!(blockchain.compact_blocks.is_empty());
+    }
+    #[test]
+    fn test_balance_at() {
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let manual = ManualClock::new(60_000_000_000);
+        let mut blockchain = Blockchain::new(SharedClock::new(manual.clone()));
+        let key = Key::generate();
+        for _ in 0..3 {
+            manual.advance(BLOCK_TIME as i64 * 1_000);
+            let timestamp = blockchain.clock.timestamp();
+            blockchain.forge_block(&db, &key, timestamp, 10);
+        }
+        let address = key.address_bytes();
+        let height = blockchain.height();
+        assert_eq!(
+            blockchain.balance_at(&db, &address, height).unwrap(),
+            blockchain.balance(&address)
+        );
+        assert!(
+            blockchain.balance_at(&db, &address, height - 1).unwrap()
+                < blockchain.balance(&address)
+        );
+        assert!(matches!(
+            blockchain.balance_at(&db, &address, height + 1),
+            Err(Error::Height)
+        ));
+    }
 }
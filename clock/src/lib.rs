@@ -0,0 +1,189 @@
+use chrono::Utc;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+pub const NETWORK_TIME_MIN_SAMPLES: usize = 5;
+pub const NETWORK_TIME_MAX_SAMPLES: usize = 200;
+pub const NETWORK_TIME_MAX_OFFSET: i64 = 5_000;
+pub fn system_millis() -> i64 {
+    Utc::now().timestamp_millis()
+}
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn timestamp_millis(&self) -> i64;
+    fn timestamp(&self) -> u32 {
+        (self.timestamp_millis() / 1000) as u32
+    }
+}
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn timestamp_millis(&self) -> i64 {
+        system_millis()
+    }
+}
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    millis: Arc<AtomicI64>,
+}
+impl ManualClock {
+    pub fn new(millis: i64) -> ManualClock {
+        ManualClock {
+            millis: Arc::new(AtomicI64::new(millis)),
+        }
+    }
+    pub fn set(&self, millis: i64) {
+        self.millis.store(millis, Ordering::Relaxed);
+    }
+    pub fn advance(&self, millis: i64) {
+        self.millis.fetch_add(millis, Ordering::Relaxed);
+    }
+}
+impl Clock for ManualClock {
+    fn timestamp_millis(&self) -> i64 {
+        self.millis.load(Ordering::Relaxed)
+    }
+}
+#[derive(Debug, Clone)]
+pub struct SharedClock(Arc<dyn Clock>);
+impl SharedClock {
+    pub fn new(clock: impl Clock + 'static) -> SharedClock {
+        SharedClock(Arc::new(clock))
+    }
+}
+impl Default for SharedClock {
+    fn default() -> SharedClock {
+        SharedClock::new(SystemClock)
+    }
+}
+impl PartialEq for SharedClock {
+    fn eq(&self, other: &SharedClock) -> bool {
+        Arc::as_ptr(&self.0) as *const () == Arc::as_ptr(&other.0) as *const ()
+    }
+}
+impl Clock for SharedClock {
+    fn timestamp_millis(&self) -> i64 {
+        self.0.timestamp_millis()
+    }
+}
+#[derive(Debug, Clone, Default)]
+pub struct OffsetClock {
+    inner: SharedClock,
+    offset: Arc<AtomicI64>,
+}
+impl OffsetClock {
+    pub fn new(inner: SharedClock) -> OffsetClock {
+        OffsetClock {
+            inner,
+            offset: Arc::new(AtomicI64::new(0)),
+        }
+    }
+    pub fn inner(&self) -> &SharedClock {
+        &self.inner
+    }
+    pub fn offset(&self) -> i64 {
+        self.offset.load(Ordering::Relaxed)
+    }
+    pub fn set_offset(&self, millis: i64) {
+        self.offset.store(millis, Ordering::Relaxed);
+    }
+}
+impl Clock for OffsetClock {
+    fn timestamp_millis(&self) -> i64 {
+        self.inner.timestamp_millis() + self.offset()
+    }
+}
+#[derive(Debug, Clone)]
+pub struct NetworkTime<P> {
+    samples: HashMap<P, i64>,
+}
+impl<P: Clone + Eq + Hash> Default for NetworkTime<P> {
+    fn default() -> NetworkTime<P> {
+        NetworkTime {
+            samples: HashMap::new(),
+        }
+    }
+}
+impl<P: Clone + Eq + Hash> NetworkTime<P> {
+    pub fn insert(&mut self, peer: P, offset: i64) {
+        if !self.samples.contains_key(&peer) && self.samples.len() >= NETWORK_TIME_MAX_SAMPLES {
+            return;
+        }
+        self.samples.insert(peer, offset);
+    }
+    pub fn remove(&mut self, peer: &P) {
+        self.samples.remove(peer);
+    }
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+    pub fn median(&self) -> Option<i64> {
+        if self.samples.len() < NETWORK_TIME_MIN_SAMPLES {
+            return None;
+        }
+        let mut vec: Vec<i64> = self.samples.values().copied().collect();
+        vec.sort_unstable();
+        let i = vec.len() / 2;
+        match vec.len() % 2 {
+            0 => Some((vec[i - 1] + vec[i]) / 2),
+            _ => Some(vec[i]),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_manual_clock() {
+        let manual = ManualClock::new(1_000);
+        let clock = SharedClock::new(manual.clone());
+        assert_eq!(clock.timestamp(), 1);
+        manual.advance(3_600_000);
+        assert_eq!(clock.timestamp_millis(), 3_601_000);
+        manual.set(0);
+        assert_eq!(clock.timestamp(), 0);
+        assert_eq!(clock, clock.clone());
+        assert_ne!(clock, SharedClock::default());
+    }
+    #[test]
+    fn test_offset_clock() {
+        let manual = ManualClock::new(10_000);
+        let clock = OffsetClock::new(SharedClock::new(manual.clone()));
+        let shared = SharedClock::new(clock.clone());
+        clock.set_offset(-2_500);
+        assert_eq!(shared.timestamp_millis(), 7_500);
+        assert_eq!(clock.inner().timestamp_millis(), 10_000);
+        manual.advance(1_000);
+        assert_eq!(clock.timestamp_millis(), 8_500);
+        assert_eq!(clock.offset(), -2_500);
+    }
+    #[test]
+    fn test_median() {
+        let mut network_time = NetworkTime::default();
+        for (peer, offset) in [(1, 100), (2, -50), (3, 20), (4, 5000)] {
+            network_time.insert(peer, offset);
+        }
+        assert_eq!(network_time.median(), None);
+        network_time.insert(5, 0);
+        assert_eq!(network_time.median(), Some(20));
+        network_time.insert(6, 40);
+        assert_eq!(network_time.median(), Some(30));
+        network_time.insert(6, -40);
+        assert_eq!(network_time.median(), Some(10));
+        network_time.remove(&4);
+        assert_eq!(network_time.median(), Some(0));
+    }
+    #[test]
+    fn test_max_samples() {
+        let mut network_time = NetworkTime::default();
+        for peer in 0..NETWORK_TIME_MAX_SAMPLES + 10 {
+            network_time.insert(peer, 0);
+        }
+        assert_eq!(network_time.len(), NETWORK_TIME_MAX_SAMPLES);
+    }
+}
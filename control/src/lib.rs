@@ -8,11 +8,15 @@ use tracing_subscriber::reload::Handle;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::Registry;
 pub fn spawn(handle: Handle<EnvFilter, Registry>, addr: &SocketAddr) {
+    spawn_with(handle, addr, Router::new())
+}
+pub fn spawn_with(handle: Handle<EnvFilter, Registry>, addr: &SocketAddr, router: Router) {
     let builder = Server::bind(addr);
     let router = Router::new()
         .route("/", post(handler))
-        .layer(TraceLayer::new_for_http())
-        .with_state(handle);
+        .with_state(handle)
+        .merge(router)
+        .layer(TraceLayer::new_for_http());
     let make_service = router.into_make_service();
     tokio::spawn(async { builder.serve(make_service).await });
 }
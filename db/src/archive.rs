@@ -0,0 +1,89 @@
+use crate::Error;
+use rocksdb::ColumnFamily;
+use rocksdb::Direction;
+use rocksdb::IteratorMode;
+use rocksdb::DB;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::instrument;
+pub fn cf(db: &DB) -> &ColumnFamily {
+    db.cf_handle("archive").unwrap()
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put(db: &DB, address: &[u8; 20], height: usize, archive: &ArchiveDB) -> Result<(), Error> {
+    let key = key(address, height);
+    let value = bincode::serialize(archive).map_err(Error::Bincode)?;
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put_height(db: &DB, height: usize) -> Result<(), Error> {
+    let key = [];
+    let value = bincode::serialize(&height).map_err(Error::Bincode)?;
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn height(db: &DB) -> Result<usize, Error> {
+    let key = [];
+    match db.get_cf(cf(db), key).map_err(Error::RocksDB)? {
+        Some(vec) => bincode::deserialize(&vec).map_err(Error::Bincode),
+        None => Ok(0),
+    }
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get(db: &DB, address: &[u8; 20], height: usize) -> Result<ArchiveDB, Error> {
+    let from = key(address, height);
+    let mode = IteratorMode::From(&from, Direction::Reverse);
+    match db.iterator_cf(cf(db), mode).next() {
+        Some(res) => {
+            let (key, value) = res.map_err(Error::RocksDB)?;
+            if key.len() != 28 || !key.starts_with(address) {
+                return Ok(ArchiveDB::default());
+            }
+            bincode::deserialize(&value).map_err(Error::Bincode)
+        }
+        None => Ok(ArchiveDB::default()),
+    }
+}
+fn key(address: &[u8; 20], height: usize) -> [u8; 28] {
+    let mut key = [0; 28];
+    key[0..20].copy_from_slice(address);
+    key[20..28].copy_from_slice(&(height as u64).to_be_bytes());
+    key
+}
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveDB {
+    pub balance: u128,
+    pub staked: u128,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    #[test]
+    fn test_get() {
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = crate::open_cf_descriptors(tempdir.path());
+        let archive = |balance| ArchiveDB { balance, staked: 0 };
+        put(&db, &[1; 20], 5, &archive(50)).unwrap();
+        put(&db, &[1; 20], 10, &archive(100)).unwrap();
+        put(&db, &[2; 20], 3, &archive(30)).unwrap();
+        assert_eq!(get(&db, &[1; 20], 4).unwrap(), ArchiveDB::default());
+        assert_eq!(get(&db, &[1; 20], 5).unwrap(), archive(50));
+        assert_eq!(get(&db, &[1; 20], 9).unwrap(), archive(50));
+        assert_eq!(get(&db, &[1; 20], 10).unwrap(), archive(100));
+        assert_eq!(get(&db, &[1; 20], usize::MAX).unwrap(), archive(100));
+        assert_eq!(get(&db, &[2; 20], 2).unwrap(), ArchiveDB::default());
+        assert_eq!(get(&db, &[2; 20], 3).unwrap(), archive(30));
+        assert_eq!(get(&db, &[0; 20], 10).unwrap(), ArchiveDB::default());
+        assert_eq!(get(&db, &[3; 20], 10).unwrap(), ArchiveDB::default());
+    }
+    #[test]
+    fn test_height() {
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = crate::open_cf_descriptors(tempdir.path());
+        assert_eq!(height(&db).unwrap(), 0);
+        put_height(&db, 42).unwrap();
+        assert_eq!(height(&db).unwrap(), 42);
+        assert_eq!(get(&db, &[0; 20], 0).unwrap(), ArchiveDB::default());
+    }
+}
@@ -1,7 +1,9 @@
 use crate::stake;
 use crate::transaction;
+use crate::transaction_block;
 use crate::Error;
 use block::Block;
+use block::Header;
 use rocksdb::ColumnFamily;
 use rocksdb::DB;
 use serde::Deserialize;
@@ -13,13 +15,14 @@ pub fn cf(db: &DB) -> &ColumnFamily {
 }
 #[instrument(skip_all, level = "trace")]
 pub fn put(db: &DB, block: &Block) -> Result<(), Error> {
+    let key = block.hash();
     for transaction in block.transactions.iter() {
         transaction::put(db, transaction)?;
+        transaction_block::put(db, &transaction.hash(), &key)?;
     }
     for stake in block.stakes.iter() {
         stake::put(db, stake)?;
     }
-    let key = block.hash();
     let value = bincode::serialize(&BlockDB::from(block)).map_err(Error::Bincode)?;
     db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
 }
@@ -48,6 +51,23 @@ pub fn get(db: &DB, hash: &[u8]) -> Result<Block, Error> {
         stakes,
     })
 }
+#[instrument(skip_all, level = "trace")]
+pub fn get_header(db: &DB, hash: &[u8]) -> Result<Header, Error> {
+    let key = hash;
+    let vec = db
+        .get_cf(cf(db), key)
+        .map_err(Error::RocksDB)?
+        .ok_or(Error::NotFound)?;
+    let block_db: BlockDB = bincode::deserialize(&vec).map_err(Error::Bincode)?;
+    Ok(Header {
+        previous_hash: block_db.previous_hash,
+        transaction_root: Block::merkle_root(&block_db.transaction_hashes),
+        stake_root: Block::merkle_root(&block_db.stake_hashes),
+        timestamp: block_db.timestamp,
+        signature: block_db.signature,
+        pi: block_db.pi,
+    })
+}
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct BlockDB {
     pub previous_hash: [u8; 32],
@@ -0,0 +1,227 @@
+use crate::Error;
+use block::Block;
+use rocksdb::ColumnFamily;
+use rocksdb::IteratorMode;
+use rocksdb::DB;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+use tracing::instrument;
+pub fn cf(db: &DB) -> &ColumnFamily {
+    db.cf_handle("history").unwrap()
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put(db: &DB, address: &[u8; 20], history: &HistoryDB) -> Result<(), Error> {
+    let key = key(address, history.height, &history.hash);
+    let value =
+        bincode::serialize(&(history.direction, history.block_hash)).map_err(Error::Bincode)?;
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn delete(db: &DB, address: &[u8; 20], history: &HistoryDB) -> Result<(), Error> {
+    let key = key(address, history.height, &history.hash);
+    db.delete_cf(cf(db), key).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put_block(db: &DB, block: &Block, height: usize) -> Result<(), Error> {
+    for (address, history) in HistoryDB::from_block(block, height) {
+        put(db, &address, &history)?;
+    }
+    Ok(())
+}
+#[instrument(skip_all, level = "trace")]
+pub fn delete_block(db: &DB, block: &Block, height: usize) -> Result<(), Error> {
+    for (address, history) in HistoryDB::from_block(block, height) {
+        delete(db, &address, &history)?;
+    }
+    Ok(())
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put_height(db: &DB, height: usize) -> Result<(), Error> {
+    let key = [];
+    let value = bincode::serialize(&height).map_err(Error::Bincode)?;
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn height(db: &DB) -> Result<usize, Error> {
+    let key = [];
+    match db.get_cf(cf(db), key).map_err(Error::RocksDB)? {
+        Some(vec) => bincode::deserialize(&vec).map_err(Error::Bincode),
+        None => Ok(0),
+    }
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get(
+    db: &DB,
+    address: &[u8; 20],
+    max_height: usize,
+    skip: usize,
+    take: usize,
+) -> Result<Vec<HistoryDB>, Error> {
+    let from = key(address, max_height, &[0xff; 32]);
+    let mode = IteratorMode::From(&from, rocksdb::Direction::Reverse);
+    let mut vec = vec![];
+    for res in db.iterator_cf(cf(db), mode).skip(skip).take(take) {
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        if key.len() != 60 || !key.starts_with(address) {
+            break;
+        }
+        let (direction, block_hash) = bincode::deserialize(&value).map_err(Error::Bincode)?;
+        vec.push(HistoryDB {
+            height: u64::from_be_bytes(key[20..28].try_into().unwrap()) as usize,
+            hash: key[28..60].try_into().unwrap(),
+            block_hash,
+            direction,
+        });
+    }
+    Ok(vec)
+}
+fn key(address: &[u8; 20], height: usize, hash: &[u8; 32]) -> [u8; 60] {
+    let mut key = [0; 60];
+    key[0..20].copy_from_slice(address);
+    key[20..28].copy_from_slice(&(height as u64).to_be_bytes());
+    key[28..60].copy_from_slice(hash);
+    key
+}
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    TransactionInput,
+    TransactionOutput,
+    StakeDeposit,
+    StakeWithdraw,
+    TransactionSelf,
+}
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Direction::TransactionInput => "transaction_input",
+            Direction::TransactionOutput => "transaction_output",
+            Direction::StakeDeposit => "stake_deposit",
+            Direction::StakeWithdraw => "stake_withdraw",
+            Direction::TransactionSelf => "transaction_self",
+        };
+        write!(f, "{}", str)
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryDB {
+    pub height: usize,
+    pub hash: [u8; 32],
+    pub block_hash: [u8; 32],
+    pub direction: Direction,
+}
+impl HistoryDB {
+    pub fn from_block(block: &Block, height: usize) -> Vec<([u8; 20], HistoryDB)> {
+        let block_hash = block.hash();
+        let mut vec = vec![];
+        for transaction in block.transactions.iter() {
+            let hash = transaction.hash();
+            let input_address = transaction.input_address().ok();
+            if input_address == Some(transaction.output_address) {
+                vec.push((
+                    transaction.output_address,
+                    HistoryDB {
+                        height,
+                        hash,
+                        block_hash,
+                        direction: Direction::TransactionSelf,
+                    },
+                ));
+                continue;
+            }
+            if let Some(input_address) = input_address {
+                vec.push((
+                    input_address,
+                    HistoryDB {
+                        height,
+                        hash,
+                        block_hash,
+                        direction: Direction::TransactionInput,
+                    },
+                ));
+            }
+            vec.push((
+                transaction.output_address,
+                HistoryDB {
+                    height,
+                    hash,
+                    block_hash,
+                    direction: Direction::TransactionOutput,
+                },
+            ));
+        }
+        for stake in block.stakes.iter() {
+            if let Ok(input_address) = stake.input_address() {
+                vec.push((
+                    input_address,
+                    HistoryDB {
+                        height,
+                        hash: stake.hash(),
+                        block_hash,
+                        direction: if stake.deposit {
+                            Direction::StakeDeposit
+                        } else {
+                            Direction::StakeWithdraw
+                        },
+                    },
+                ));
+            }
+        }
+        vec
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use key::Key;
+    use tempdir::TempDir;
+    use transaction::Transaction;
+    #[test]
+    fn test_history() {
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = crate::open_cf_descriptors(tempdir.path());
+        assert_eq!(height(&db).unwrap(), 0);
+        let address = [1; 20];
+        let history = |height, hash| HistoryDB {
+            height,
+            hash: [hash; 32],
+            block_hash: [height as u8; 32],
+            direction: Direction::TransactionOutput,
+        };
+        for (height, hash) in [(1, 1), (2, 2), (2, 3), (3, 4)] {
+            put(&db, &address, &history(height, hash)).unwrap();
+        }
+        put(&db, &[2; 20], &history(2, 5)).unwrap();
+        put_height(&db, 3).unwrap();
+        assert_eq!(height(&db).unwrap(), 3);
+        assert_eq!(
+            get(&db, &address, 3, 0, 10).unwrap(),
+            vec![history(3, 4), history(2, 3), history(2, 2), history(1, 1)]
+        );
+        assert_eq!(
+            get(&db, &address, 2, 1, 2).unwrap(),
+            vec![history(2, 2), history(1, 1)]
+        );
+        delete(&db, &address, &history(2, 3)).unwrap();
+        assert_eq!(
+            get(&db, &address, 3, 0, 10).unwrap(),
+            vec![history(3, 4), history(2, 2), history(1, 1)]
+        );
+        assert_eq!(get(&db, &[0; 20], 3, 0, 10).unwrap(), vec![]);
+        assert_eq!(get(&db, &[2; 20], 3, 0, 10).unwrap(), vec![history(2, 5)]);
+    }
+    #[test]
+    fn test_from_block_self() {
+        let key = Key::generate();
+        let address = key.address_bytes();
+        let transaction = Transaction::sign(address, 1, 1, 0, &key).unwrap();
+        let block = Block {
+            transactions: vec![transaction],
+            ..Default::default()
+        };
+        let vec = HistoryDB::from_block(&block, 1);
+        assert_eq!(vec.len(), 1);
+        assert_eq!(vec[0].0, address);
+        assert_eq!(vec[0].1.direction, Direction::TransactionSelf);
+    }
+}
@@ -1,8 +1,12 @@
+pub mod archive;
 pub mod block;
 pub mod checkpoint;
+pub mod history;
 pub mod peer;
+pub mod score;
 pub mod stake;
 pub mod transaction;
+pub mod transaction_block;
 pub mod tree;
 use rocksdb::ColumnFamilyDescriptor;
 use rocksdb::Options;
@@ -24,7 +28,11 @@ pub fn open_cf_descriptors(path: impl AsRef<Path>) -> DB {
         ColumnFamilyDescriptor::new("transaction", options.clone()),
         ColumnFamilyDescriptor::new("stake", options.clone()),
         ColumnFamilyDescriptor::new("peer", options.clone()),
-        ColumnFamilyDescriptor::new("checkpoint", options),
+        ColumnFamilyDescriptor::new("checkpoint", options.clone()),
+        ColumnFamilyDescriptor::new("transaction_block", options.clone()),
+        ColumnFamilyDescriptor::new("history", options.clone()),
+        ColumnFamilyDescriptor::new("archive", options.clone()),
+        ColumnFamilyDescriptor::new("score", options),
     ];
     DB::open_cf_descriptors(&opts, path, cfs).unwrap()
 }
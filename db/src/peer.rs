@@ -2,23 +2,203 @@ use crate::Error;
 use rocksdb::ColumnFamily;
 use rocksdb::IteratorMode;
 use rocksdb::DB;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
 use std::net::IpAddr;
 use tracing::instrument;
 pub fn cf(db: &DB) -> &ColumnFamily {
     db.cf_handle("peer").unwrap()
 }
 #[instrument(skip_all, level = "trace")]
-pub fn put(db: &DB, ip_addr: &IpAddr) -> Result<(), Error> {
-    let key = bincode::serialize(ip_addr).map_err(Error::Bincode)?;
-    let value = [];
+pub fn put(db: &DB, address: &str, peer: &PeerDB) -> Result<(), Error> {
+    let key = address.as_bytes();
+    let value = bincode::serialize(peer).map_err(Error::Bincode)?;
     db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
 }
+#[instrument(skip_all, level = "trace")]
+pub fn get(db: &DB, address: &str) -> Result<Option<PeerDB>, Error> {
+    match db
+        .get_cf(cf(db), address.as_bytes())
+        .map_err(Error::RocksDB)?
+    {
+        Some(value) => Ok(Some(bincode::deserialize(&value).map_err(Error::Bincode)?)),
+        None => Ok(None),
+    }
+}
+#[instrument(skip_all, level = "trace")]
+pub fn delete(db: &DB, address: &str) -> Result<(), Error> {
+    db.delete_cf(cf(db), address.as_bytes())
+        .map_err(Error::RocksDB)
+}
 #[instrument(skip_all, level = "debug")]
-pub fn get_all(db: &DB) -> Result<Vec<IpAddr>, Error> {
-    let mut peers: Vec<IpAddr> = vec![];
+pub fn get_all(db: &DB) -> Result<Vec<(String, PeerDB)>, Error> {
+    let mut peers = vec![];
     for res in db.iterator_cf(cf(db), IteratorMode::Start) {
-        let (peer, _) = res.map_err(Error::RocksDB)?;
-        peers.push(bincode::deserialize(&peer).map_err(Error::Bincode)?);
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        if let (Ok(address), Ok(peer)) = (
+            String::from_utf8(key.to_vec()),
+            bincode::deserialize(&value),
+        ) {
+            peers.push((address, peer));
+        }
     }
     Ok(peers)
 }
+#[instrument(skip_all, level = "debug")]
+pub fn migrate(db: &DB, address: impl Fn(IpAddr, Option<u16>) -> String) -> Result<usize, Error> {
+    let mut vec = vec![];
+    for res in db.iterator_cf(cf(db), IteratorMode::Start) {
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        if key.starts_with(b"/") {
+            continue;
+        }
+        let ip_addr: IpAddr = match bincode::deserialize(&key) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        let legacy = match value.is_empty() {
+            true => LegacyPeerDB::default(),
+            false => match bincode::deserialize(&value) {
+                Ok(x) => x,
+                Err(_) => continue,
+            },
+        };
+        vec.push((key, ip_addr, legacy));
+    }
+    for (key, ip_addr, legacy) in vec.iter() {
+        let peer = PeerDB {
+            source: legacy.source,
+            last_success: legacy.last_success,
+            last_failure: legacy.last_failure,
+            failures: legacy.failures,
+        };
+        put(db, &address(*ip_addr, legacy.port), &peer)?;
+        db.delete_cf(cf(db), key).map_err(Error::RocksDB)?;
+    }
+    Ok(vec.len())
+}
+#[instrument(skip_all, level = "debug")]
+pub fn prune(db: &DB) -> Result<usize, Error> {
+    let mut keys = vec![];
+    for res in db.iterator_cf(cf(db), IteratorMode::Start) {
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        if !key.starts_with(b"/")
+            || std::str::from_utf8(&key).is_err()
+            || bincode::deserialize::<PeerDB>(&value).is_err()
+        {
+            keys.push(key);
+        }
+    }
+    for key in keys.iter() {
+        db.delete_cf(cf(db), key).map_err(Error::RocksDB)?;
+    }
+    Ok(keys.len())
+}
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Source {
+    #[default]
+    Unknown,
+    Config,
+    Inbound,
+    Mdns,
+    Gossip,
+    Exchange,
+    Api,
+    Identify,
+    Kademlia,
+}
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Source::Unknown => "unknown",
+            Source::Config => "config",
+            Source::Inbound => "inbound",
+            Source::Mdns => "mdns",
+            Source::Gossip => "gossip",
+            Source::Exchange => "exchange",
+            Source::Api => "api",
+            Source::Identify => "identify",
+            Source::Kademlia => "kademlia",
+        };
+        write!(f, "{}", str)
+    }
+}
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PeerDB {
+    pub source: Source,
+    pub last_success: u32,
+    pub last_failure: u32,
+    pub failures: u32,
+}
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+struct LegacyPeerDB {
+    port: Option<u16>,
+    source: Source,
+    last_success: u32,
+    last_failure: u32,
+    failures: u32,
+}
+impl PeerDB {
+    pub fn new(source: Source) -> PeerDB {
+        PeerDB {
+            source,
+            ..Default::default()
+        }
+    }
+    pub fn backoff(&self, base: u32, max: u32) -> u32 {
+        match self.failures {
+            0 => 0,
+            failures => base.saturating_mul(1 << (failures - 1).min(31)).min(max),
+        }
+    }
+    pub fn dialable(&self, timestamp: u32, base: u32, max: u32) -> bool {
+        timestamp >= self.last_failure.saturating_add(self.backoff(base, max))
+    }
+    pub fn stale(&self, timestamp: u32, failures: u32, forget: u32) -> bool {
+        self.failures >= failures && timestamp.saturating_sub(self.last_success) > forget
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    #[test]
+    fn test_migrate() {
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = crate::open_cf_descriptors(tempdir.path());
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "::1".parse().unwrap();
+        let legacy = LegacyPeerDB {
+            port: Some(1234),
+            source: Source::Api,
+            last_success: 1,
+            last_failure: 2,
+            failures: 3,
+        };
+        db.put_cf(cf(&db), bincode::serialize(&a).unwrap(), [])
+            .unwrap();
+        db.put_cf(
+            cf(&db),
+            bincode::serialize(&b).unwrap(),
+            bincode::serialize(&legacy).unwrap(),
+        )
+        .unwrap();
+        put(&db, "/ip4/10.0.0.2/tcp/2020", &PeerDB::new(Source::Config)).unwrap();
+        let address =
+            |ip_addr: IpAddr, port: Option<u16>| format!("/{}/{}", ip_addr, port.unwrap_or(2020));
+        assert_eq!(migrate(&db, address).unwrap(), 2);
+        assert_eq!(prune(&db).unwrap(), 0);
+        assert_eq!(get(&db, "/10.0.0.1/2020").unwrap(), Some(PeerDB::default()));
+        assert_eq!(
+            get(&db, "/::1/1234").unwrap(),
+            Some(PeerDB {
+                source: Source::Api,
+                last_success: 1,
+                last_failure: 2,
+                failures: 3,
+            })
+        );
+        assert_eq!(get_all(&db).unwrap().len(), 3);
+    }
+}
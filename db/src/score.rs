@@ -0,0 +1,68 @@
+use crate::Error;
+use rocksdb::ColumnFamily;
+use rocksdb::IteratorMode;
+use rocksdb::DB;
+use serde::Deserialize;
+use serde::Serialize;
+use std::net::IpAddr;
+use tracing::instrument;
+pub fn cf(db: &DB) -> &ColumnFamily {
+    db.cf_handle("score").unwrap()
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put(db: &DB, ip_addr: &IpAddr, score: &ScoreDB) -> Result<(), Error> {
+    let key = bincode::serialize(ip_addr).map_err(Error::Bincode)?;
+    let value = bincode::serialize(score).map_err(Error::Bincode)?;
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get(db: &DB, ip_addr: &IpAddr) -> Result<ScoreDB, Error> {
+    let key = bincode::serialize(ip_addr).map_err(Error::Bincode)?;
+    match db.get_cf(cf(db), key).map_err(Error::RocksDB)? {
+        Some(value) => bincode::deserialize(&value).map_err(Error::Bincode),
+        None => Ok(ScoreDB::default()),
+    }
+}
+#[instrument(skip_all, level = "trace")]
+pub fn delete(db: &DB, ip_addr: &IpAddr) -> Result<(), Error> {
+    let key = bincode::serialize(ip_addr).map_err(Error::Bincode)?;
+    db.delete_cf(cf(db), key).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "debug")]
+pub fn get_all(db: &DB) -> Result<Vec<(IpAddr, ScoreDB)>, Error> {
+    let mut vec = vec![];
+    for res in db.iterator_cf(cf(db), IteratorMode::Start) {
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        vec.push((
+            bincode::deserialize(&key).map_err(Error::Bincode)?,
+            bincode::deserialize(&value).map_err(Error::Bincode)?,
+        ));
+    }
+    Ok(vec)
+}
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScoreDB {
+    pub invalid: u32,
+    pub ratelimited: u32,
+    pub useful: u32,
+    pub uptime: u32,
+    pub banned_until: u32,
+}
+impl ScoreDB {
+    pub fn score(&self) -> i64 {
+        self.useful as i64 + self.uptime as i64 / 10
+            - self.invalid as i64 * 10
+            - self.ratelimited as i64 * 2
+    }
+    pub fn banned(&self, timestamp: u32) -> bool {
+        self.banned_until > timestamp
+    }
+    pub fn neutral(&self) -> bool {
+        self.invalid == 0 && self.ratelimited == 0
+    }
+    pub fn ban(&mut self, until: u32) {
+        self.banned_until = until;
+        self.invalid = 0;
+        self.ratelimited = 0;
+    }
+}
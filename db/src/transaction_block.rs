@@ -0,0 +1,78 @@
+use crate::block;
+use crate::block::BlockDB;
+use crate::Error;
+use rocksdb::ColumnFamily;
+use rocksdb::Direction;
+use rocksdb::IteratorMode;
+use rocksdb::DB;
+use tracing::instrument;
+pub fn cf(db: &DB) -> &ColumnFamily {
+    db.cf_handle("transaction_block").unwrap()
+}
+#[instrument(skip_all, level = "trace")]
+pub fn put(db: &DB, transaction_hash: &[u8; 32], block_hash: &[u8; 32]) -> Result<(), Error> {
+    let mut key = [0; 64];
+    key[0..32].copy_from_slice(transaction_hash);
+    key[32..64].copy_from_slice(block_hash);
+    let value = [];
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn get(db: &DB, transaction_hash: &[u8; 32]) -> Result<Vec<[u8; 32]>, Error> {
+    let mut block_hashes = vec![];
+    let mode = IteratorMode::From(transaction_hash, Direction::Forward);
+    for res in db.iterator_cf(cf(db), mode) {
+        let (key, _) = res.map_err(Error::RocksDB)?;
+        if key.len() != 64 || !key.starts_with(transaction_hash) {
+            break;
+        }
+        block_hashes.push(key[32..64].try_into().unwrap());
+    }
+    Ok(block_hashes)
+}
+#[instrument(skip_all, level = "trace")]
+pub fn backfill(db: &DB) -> Result<usize, Error> {
+    let key = [];
+    if db.get_cf(cf(db), key).map_err(Error::RocksDB)?.is_some() {
+        return Ok(0);
+    }
+    let mut len = 0;
+    for res in db.iterator_cf(block::cf(db), IteratorMode::Start) {
+        let (key, value) = res.map_err(Error::RocksDB)?;
+        let block_hash: [u8; 32] = match key.as_ref().try_into() {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        let block_db: BlockDB = bincode::deserialize(&value).map_err(Error::Bincode)?;
+        for transaction_hash in block_db.transaction_hashes.iter() {
+            put(db, transaction_hash, &block_hash)?;
+            len += 1;
+        }
+    }
+    let value = [];
+    db.put_cf(cf(db), key, value).map_err(Error::RocksDB)?;
+    Ok(len)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::block::Block;
+    use tempdir::TempDir;
+    use transaction::Transaction;
+    #[test]
+    fn test_backfill() {
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = crate::open_cf_descriptors(tempdir.path());
+        let block = Block {
+            transactions: vec![Transaction::default()],
+            ..Default::default()
+        };
+        let value = bincode::serialize(&BlockDB::from(&block)).unwrap();
+        db.put_cf(block::cf(&db), block.hash(), value).unwrap();
+        let transaction_hash = block.transactions[0].hash();
+        assert!(get(&db, &transaction_hash).unwrap().is_empty());
+        assert_eq!(backfill(&db).unwrap(), 1);
+        assert_eq!(get(&db, &transaction_hash).unwrap(), vec![block.hash()]);
+        assert_eq!(backfill(&db).unwrap(), 0);
+    }
+}
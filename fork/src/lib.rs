@@ -1,5 +1,6 @@
 use address::public;
 use block::Block;
+use db::archive::ArchiveDB;
 use db::checkpoint::CheckpointDB;
 use decimal::Decimal;
 use rocksdb::DB;
@@ -22,6 +23,10 @@ pub const ELAPSED: u32 = 90;
 pub enum Error {
     NotAllowedToForkStableChain,
     Overflow,
+    DB(db::Error),
+    Block(block::Error),
+    Transaction(transaction::Error),
+    Stake(stake::Error),
 }
 pub trait Fork {
     fn get_hashes_mut(&mut self) -> &mut Vec<[u8; 32]>;
@@ -292,19 +297,50 @@ impl Manager {
         let unstable = Unstable::from(db, &hashes, &self.stable);
         Ok(unstable)
     }
-    pub fn update(&mut self, db: &DB, hashes_1: &[[u8; 32]], trust_fork_after_blocks: usize) {
-        let hashes_0 = &self.unstable.hashes;
-        if hashes_0.len() == trust_fork_after_blocks {
-            let block = db::block::get(db, hashes_0.first().unwrap()).unwrap();
-            self.stable.append_block(
-                &block,
-                match db::block::get(db, &block.previous_hash) {
-                    Ok(block) => block.timestamp,
-                    Err(_) => 0,
-                },
-            );
+    pub fn update(
+        &mut self,
+        db: &DB,
+        hashes_1: &[[u8; 32]],
+        trust_fork_after_blocks: usize,
+    ) -> Result<(), Error> {
+        let block = match self.unstable.hashes.first() {
+            Some(hash) if self.unstable.hashes.len() == trust_fork_after_blocks => {
+                Some(db::block::get(db, hash).map_err(Error::DB)?)
+            }
+            _ => None,
+        };
+        if let Some(block) = &block {
+            let previous_timestamp = match db::block::get(db, &block.previous_hash) {
+                Ok(block) => block.timestamp,
+                Err(_) => 0,
+            };
+            self.stable.append_block(db, block, previous_timestamp)?;
         }
         self.unstable = Unstable::from(db, hashes_1, &self.stable);
+        if let Some(block) = block {
+            let height = self.stable.hashes.len();
+            db::history::put_block(db, &block, height).map_err(Error::DB)?;
+            db::history::put_height(db, height).map_err(Error::DB)?;
+        }
+        Ok(())
+    }
+    pub fn rollback(&self, db: &DB) -> Result<(), db::Error> {
+        let stable_height = self.stable.hashes.len();
+        let height = db::history::height(db)?;
+        if height <= stable_height {
+            return Ok(());
+        }
+        for (index, hash) in self
+            .unstable
+            .hashes
+            .iter()
+            .enumerate()
+            .take(height - stable_height)
+        {
+            let block = db::block::get(db, hash)?;
+            db::history::delete_block(db, &block, stable_height + index + 1)?;
+        }
+        db::history::put_height(db, stable_height)
     }
 }
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
@@ -312,16 +348,97 @@ pub struct Stable {
     pub latest_block: Block,
     pub hashes: Vec<[u8; 32]>,
     pub stakers: VecDeque<[u8; 20]>,
+    pub archive: bool,
     latest_blocks: Vec<Block>,
     map_balance: HashMap<[u8; 20], u128>,
     map_staked: HashMap<[u8; 20], u128>,
 }
 impl Stable {
-    pub fn append_block(&mut self, block: &Block, previous_timestamp: u32) {
-        append_block(self, block, previous_timestamp, false)
+    pub fn append_block(
+        &mut self,
+        db: &DB,
+        block: &Block,
+        previous_timestamp: u32,
+    ) -> Result<(), Error> {
+        match self.archive {
+            true => self.append_block_archive(db, block, previous_timestamp, false),
+            false => {
+                append_block(self, block, previous_timestamp, false);
+                Ok(())
+            }
+        }
     }
-    pub fn load(&mut self, db: &DB, hashes: &[[u8; 32]]) {
-        load(self, db, hashes)
+    pub fn load(&mut self, db: &DB, hashes: &[[u8; 32]]) -> Result<(), Error> {
+        match self.archive {
+            true => self.load_archive(db, hashes)?,
+            false => load(self, db, hashes),
+        }
+        self.backfill(db).map_err(Error::DB)
+    }
+    fn load_archive(&mut self, db: &DB, hashes: &[[u8; 32]]) -> Result<(), Error> {
+        let height = db::archive::height(db).map_err(Error::DB)?;
+        let mut previous_timestamp = match hashes.first() {
+            Some(hash) => db::block::get(db, hash).map_err(Error::DB)?.timestamp,
+            None => 0,
+        };
+        for hash in hashes.iter() {
+            let block = db::block::get(db, hash).map_err(Error::DB)?;
+            match self.hashes.len() < height {
+                true => append_block(self, &block, previous_timestamp, true),
+                false => self.append_block_archive(db, &block, previous_timestamp, true)?,
+            }
+            previous_timestamp = block.timestamp;
+        }
+        db::archive::put_height(db, self.hashes.len()).map_err(Error::DB)
+    }
+    fn backfill(&self, db: &DB) -> Result<(), db::Error> {
+        let height = db::history::height(db)?;
+        if height >= self.hashes.len() {
+            return Ok(());
+        }
+        for (index, hash) in self.hashes.iter().enumerate().skip(height) {
+            let block = db::block::get(db, hash)?;
+            db::history::put_block(db, &block, index + 1)?;
+        }
+        db::history::put_height(db, self.hashes.len())
+    }
+    fn append_block_archive(
+        &mut self,
+        db: &DB,
+        block: &Block,
+        previous_timestamp: u32,
+        loading: bool,
+    ) -> Result<(), Error> {
+        let mut addresses: Vec<[u8; 20]> = self.stakers.iter().copied().collect();
+        addresses.push(block.input_address().map_err(Error::Block)?);
+        for transaction in block.transactions.iter() {
+            addresses.push(transaction.input_address().map_err(Error::Transaction)?);
+            addresses.push(transaction.output_address);
+        }
+        for stake in block.stakes.iter() {
+            addresses.push(stake.input_address().map_err(Error::Stake)?);
+        }
+        addresses.sort();
+        addresses.dedup();
+        let previous: Vec<ArchiveDB> = addresses
+            .iter()
+            .map(|address| ArchiveDB {
+                balance: get_balance(self, address),
+                staked: get_staked(self, address),
+            })
+            .collect();
+        append_block(self, block, previous_timestamp, loading);
+        let height = self.hashes.len();
+        for (address, previous) in addresses.iter().zip(previous) {
+            let archive = ArchiveDB {
+                balance: get_balance(self, address),
+                staked: get_staked(self, address),
+            };
+            if archive != previous {
+                db::archive::put(db, address, height, &archive).map_err(Error::DB)?;
+            }
+        }
+        db::archive::put_height(db, height).map_err(Error::DB)
     }
     pub fn checkpoint(&self) -> CheckpointDB {
         CheckpointDB {
@@ -338,6 +455,7 @@ impl Stable {
             latest_block: checkpoint.latest_block,
             hashes,
             stakers: checkpoint.stakers,
+            archive: false,
             latest_blocks: checkpoint.latest_blocks,
             map_balance: checkpoint.map_balance,
             map_staked: checkpoint.map_staked,
@@ -17,9 +17,17 @@ pub fn read(path: impl AsRef<Path>) -> [u8; 92] {
     vec.try_into().unwrap()
 }
 pub fn write(rng: &mut impl CryptoRngCore, key: &Key, filename: &str, pwd: &str) {
-    let encrypted = encryption::encrypt(rng, key.secret_key_bytes(), pwd);
     let mut path = DEFAULT_PATH.join(filename);
     path.set_extension(EXTENSION);
+    write_secret(rng, &key.secret_key_bytes(), path, pwd);
+}
+pub fn write_secret(
+    rng: &mut impl CryptoRngCore,
+    secret: &[u8; 32],
+    path: impl AsRef<Path>,
+    pwd: &str,
+) {
+    let encrypted = encryption::encrypt(rng, secret, pwd);
     let mut file = File::create(path).unwrap();
     file.write_all(hex::encode(encrypted).as_bytes()).unwrap();
 }
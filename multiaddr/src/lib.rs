@@ -1,29 +1,65 @@
 use libp2p::multiaddr::Multiaddr;
 use libp2p::multiaddr::Protocol;
 use std::net::IpAddr;
+use std::net::SocketAddr;
 pub const MAINNET_PORT: u16 = 2020;
 pub const TESTNET_PORT: u16 = 3030;
+pub const MAINNET_WEBSOCKET_PORT: u16 = 2023;
+pub const TESTNET_WEBSOCKET_PORT: u16 = 3033;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,
+    Quic,
+    Websocket,
+}
 pub trait ToMultiaddr {
     fn multiaddr(&self, testnet: bool) -> Multiaddr;
+    fn multiaddr_port(&self, port: u16) -> Multiaddr;
+    fn multiaddr_quic(&self, port: u16) -> Multiaddr;
+    fn multiaddr_websocket(&self, port: u16) -> Multiaddr;
 }
 pub trait ToIpAddr {
     fn ip_addr(&self) -> Option<IpAddr>;
 }
+pub trait ToPort {
+    fn port(&self) -> Option<u16>;
+}
+pub trait ToTransport {
+    fn transport(&self) -> Option<Transport>;
+}
 impl ToMultiaddr for IpAddr {
     fn multiaddr(&self, testnet: bool) -> Multiaddr {
         let port = if testnet { TESTNET_PORT } else { MAINNET_PORT };
-        let mut multiaddr = Multiaddr::empty();
-        match self {
-            IpAddr::V4(ip) => {
-                multiaddr.push(Protocol::Ip4(*ip));
-            }
-            IpAddr::V6(ip) => {
-                multiaddr.push(Protocol::Ip6(*ip));
-            }
-        }
+        self.multiaddr_port(port)
+    }
+    fn multiaddr_port(&self, port: u16) -> Multiaddr {
+        let mut multiaddr = ip(self);
         multiaddr.push(Protocol::Tcp(port));
         multiaddr
     }
+    fn multiaddr_quic(&self, port: u16) -> Multiaddr {
+        let mut multiaddr = ip(self);
+        multiaddr.push(Protocol::Udp(port));
+        multiaddr.push(Protocol::QuicV1);
+        multiaddr
+    }
+    fn multiaddr_websocket(&self, port: u16) -> Multiaddr {
+        let mut multiaddr = self.multiaddr_port(port);
+        multiaddr.push(Protocol::Ws("/".into()));
+        multiaddr
+    }
+}
+fn ip(ip_addr: &IpAddr) -> Multiaddr {
+    let mut multiaddr = Multiaddr::empty();
+    match ip_addr {
+        IpAddr::V4(ip) => {
+            multiaddr.push(Protocol::Ip4(*ip));
+        }
+        IpAddr::V6(ip) => {
+            multiaddr.push(Protocol::Ip6(*ip));
+        }
+    }
+    multiaddr
 }
 impl ToIpAddr for Multiaddr {
     fn ip_addr(&self) -> Option<IpAddr> {
@@ -34,6 +70,79 @@ impl ToIpAddr for Multiaddr {
         }
     }
 }
+impl ToPort for Multiaddr {
+    fn port(&self) -> Option<u16> {
+        self.iter().find_map(|a| match a {
+            Protocol::Tcp(port) => Some(port),
+            _ => None,
+        })
+    }
+}
+impl ToTransport for Multiaddr {
+    fn transport(&self) -> Option<Transport> {
+        let mut iter = self.iter().filter(|x| !matches!(x, Protocol::P2p(_)));
+        match (iter.next()?, iter.next()?, iter.next(), iter.next()) {
+            (Protocol::Ip4(_) | Protocol::Ip6(_), Protocol::Tcp(_), None, None) => {
+                Some(Transport::Tcp)
+            }
+            (
+                Protocol::Ip4(_) | Protocol::Ip6(_),
+                Protocol::Tcp(_),
+                Some(Protocol::Ws(_)),
+                None,
+            ) => Some(Transport::Websocket),
+            (
+                Protocol::Ip4(_) | Protocol::Ip6(_),
+                Protocol::Udp(_),
+                Some(Protocol::QuicV1),
+                None,
+            ) => Some(Transport::Quic),
+            _ => None,
+        }
+    }
+}
+pub fn is_global(ip_addr: &IpAddr) -> bool {
+    match ip_addr {
+        IpAddr::V4(ip) => {
+            let o = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || o[0] == 0
+                || o[0] >= 240
+                || (o[0] == 100 && o[1] & 0xc0 == 64)
+                || (o[0] == 192 && o[1] == 0 && o[2] == 0)
+                || (o[0] == 198 && o[1] & 0xfe == 18))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_global(&IpAddr::V4(ip));
+            }
+            let s = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || s[0] & 0xfe00 == 0xfc00
+                || s[0] & 0xffc0 == 0xfe80
+                || (s[0] == 0x2001 && s[1] == 0x0db8))
+        }
+    }
+}
+pub fn parse(s: &str, testnet: bool) -> Option<Multiaddr> {
+    if let Ok(ip_addr) = s.parse::<IpAddr>() {
+        return Some(ip_addr.multiaddr(testnet));
+    }
+    if let Ok(socket_addr) = s.parse::<SocketAddr>() {
+        return Some(socket_addr.ip().multiaddr_port(socket_addr.port()));
+    }
+    let multiaddr = s.parse::<Multiaddr>().ok()?;
+    multiaddr.ip_addr()?;
+    Some(multiaddr)
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,6 +180,14 @@ mod tests {
         );
     }
     #[test]
+    fn test_to_port() {
+        assert_eq!("/ip4/0.0.0.0".parse::<Multiaddr>().unwrap().port(), None);
+        assert_eq!(
+            "/ip4/0.0.0.0/tcp/1234".parse::<Multiaddr>().unwrap().port(),
+            Some(1234)
+        );
+    }
+    #[test]
     fn test_from_ip_addr() {
         assert_eq!(
             "0.0.0.0".parse::<IpAddr>().unwrap().multiaddr(false),
@@ -85,4 +202,71 @@ mod tests {
                 .unwrap()
         );
     }
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("0.0.0.0", false),
+            Some(format!("/ip4/0.0.0.0/tcp/{MAINNET_PORT}").parse().unwrap())
+        );
+        assert_eq!(
+            parse("[::1]:1234", false),
+            Some("/ip6/::1/tcp/1234".parse().unwrap())
+        );
+        assert_eq!(
+            parse("/ip4/0.0.0.0/tcp/1234", false),
+            Some("/ip4/0.0.0.0/tcp/1234".parse().unwrap())
+        );
+        assert_eq!(parse("/tcp/1234", false), None);
+        assert_eq!(parse("invalid", false), None);
+    }
+    #[test]
+    fn test_is_global() {
+        for s in ["1.1.1.1", "2606:4700:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_global(&s.parse().unwrap()), "{}", s);
+        }
+        for s in [
+            "0.0.0.0",
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.0.1",
+            "100.64.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:192.168.1.1",
+        ] {
+            assert!(!is_global(&s.parse().unwrap()), "{}", s);
+        }
+    }
+    #[test]
+    fn test_to_transport() {
+        let ip_addr = "0.0.0.0".parse::<IpAddr>().unwrap();
+        assert_eq!(
+            ip_addr.multiaddr_port(1234).transport(),
+            Some(Transport::Tcp)
+        );
+        assert_eq!(
+            ip_addr.multiaddr_quic(1234).transport(),
+            Some(Transport::Quic)
+        );
+        assert_eq!(
+            ip_addr.multiaddr_websocket(1234).transport(),
+            Some(Transport::Websocket)
+        );
+        assert_eq!(
+            "/ip4/0.0.0.0".parse::<Multiaddr>().unwrap().transport(),
+            None
+        );
+        assert_eq!(
+            "/ip4/0.0.0.0/udp/1234"
+                .parse::<Multiaddr>()
+                .unwrap()
+                .transport(),
+            None
+        );
+    }
 }
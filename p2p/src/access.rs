@@ -0,0 +1,174 @@
+use libp2p::PeerId;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Line(usize, String),
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    Ip(IpAddr),
+    Cidr(IpAddr, u8),
+    PeerId(PeerId),
+}
+impl Rule {
+    pub fn matches_ip(&self, ip_addr: &IpAddr) -> bool {
+        match self {
+            Rule::Ip(x) => x == ip_addr,
+            Rule::Cidr(x, prefix) => cidr_contains(x, *prefix, ip_addr),
+            Rule::PeerId(_) => false,
+        }
+    }
+    pub fn matches(&self, ip_addr: &IpAddr, peer_id: &PeerId) -> bool {
+        match self {
+            Rule::PeerId(x) => x == peer_id,
+            _ => self.matches_ip(ip_addr),
+        }
+    }
+}
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Ip(ip_addr) => write!(f, "{}", ip_addr),
+            Rule::Cidr(ip_addr, prefix) => write!(f, "{}/{}", ip_addr, prefix),
+            Rule::PeerId(peer_id) => write!(f, "{}", peer_id),
+        }
+    }
+}
+impl FromStr for Rule {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Rule, ()> {
+        if let Ok(ip_addr) = s.parse() {
+            return Ok(Rule::Ip(ip_addr));
+        }
+        if let Some((ip_addr, prefix)) = s.split_once('/') {
+            let ip_addr: IpAddr = ip_addr.parse().map_err(|_| ())?;
+            let prefix: u8 = prefix.parse().map_err(|_| ())?;
+            let max = match ip_addr {
+                IpAddr::V4(_) => 32,
+                IpAddr::V6(_) => 128,
+            };
+            if prefix > max {
+                return Err(());
+            }
+            return Ok(Rule::Cidr(ip_addr, prefix));
+        }
+        s.parse().map(Rule::PeerId).map_err(|_| ())
+    }
+}
+fn cidr_contains(network: &IpAddr, prefix: u8, ip_addr: &IpAddr) -> bool {
+    match (network, ip_addr) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(*a) & mask == u32::from(*b) & mask
+        }
+        (IpAddr::V6(a), IpAddr::V6(b)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(*a) & mask == u128::from(*b) & mask
+        }
+        _ => false,
+    }
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Access {
+    pub allow: Vec<Rule>,
+    pub deny: Vec<Rule>,
+}
+impl Access {
+    pub fn load(path: impl AsRef<Path>) -> Result<Access, Error> {
+        let string = fs::read_to_string(path).map_err(Error::Io)?;
+        string.parse()
+    }
+    pub fn permits(&self, ip_addr: &IpAddr, peer_id: &PeerId) -> bool {
+        if self.deny.iter().any(|x| x.matches(ip_addr, peer_id)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|x| x.matches(ip_addr, peer_id))
+    }
+    pub fn allows(&self, ip_addr: &IpAddr, peer_id: &PeerId) -> bool {
+        self.allow.iter().any(|x| x.matches(ip_addr, peer_id))
+    }
+    pub fn allows_ip(&self, ip_addr: &IpAddr) -> bool {
+        self.allow.iter().any(|x| x.matches_ip(ip_addr))
+    }
+    pub fn permits_dial(&self, ip_addr: &IpAddr) -> bool {
+        if self.deny.iter().any(|x| x.matches_ip(ip_addr)) {
+            return false;
+        }
+        self.allow.is_empty()
+            || self
+                .allow
+                .iter()
+                .any(|x| matches!(x, Rule::PeerId(_)) || x.matches_ip(ip_addr))
+    }
+}
+impl FromStr for Access {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Access, Error> {
+        let mut access = Access::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || Error::Line(i + 1, line.to_string());
+            let (list, rule) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let rule = rule.trim().parse().map_err(|_| invalid())?;
+            match list {
+                "allow" => access.allow.push(rule),
+                "deny" => access.deny.push(rule),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(access)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_rule() {
+        let ip_addr = "10.1.2.3".parse().unwrap();
+        assert!("10.1.2.3".parse::<Rule>().unwrap().matches_ip(&ip_addr));
+        assert!("10.0.0.0/8".parse::<Rule>().unwrap().matches_ip(&ip_addr));
+        assert!(!"10.0.0.0/16".parse::<Rule>().unwrap().matches_ip(&ip_addr));
+        assert!("0.0.0.0/0".parse::<Rule>().unwrap().matches_ip(&ip_addr));
+        assert!(!"::/0".parse::<Rule>().unwrap().matches_ip(&ip_addr));
+        assert!("10.0.0.0/33".parse::<Rule>().is_err());
+        assert!("invalid".parse::<Rule>().is_err());
+        assert_eq!(
+            "10.0.0.0/8".parse::<Rule>().unwrap().to_string(),
+            "10.0.0.0/8"
+        );
+    }
+    #[test]
+    fn test_access() {
+        let peer_id = PeerId::random();
+        let access: Access = format!(
+            "# consortium\nallow 10.0.0.0/8\nallow {}\ndeny 10.0.0.1 # compromised\n",
+            peer_id
+        )
+        .parse()
+        .unwrap();
+        let other = PeerId::random();
+        assert!(access.permits(&"10.0.0.2".parse().unwrap(), &other));
+        assert!(!access.permits(&"10.0.0.1".parse().unwrap(), &peer_id));
+        assert!(access.permits(&"192.168.0.1".parse().unwrap(), &peer_id));
+        assert!(!access.permits(&"192.168.0.1".parse().unwrap(), &other));
+        assert!(access.permits_dial(&"192.168.0.1".parse().unwrap()));
+        assert!(!access.permits_dial(&"10.0.0.1".parse().unwrap()));
+        assert!(access.allows(&"192.168.0.1".parse().unwrap(), &peer_id));
+        assert!(access.allows_ip(&"10.0.0.2".parse().unwrap()));
+        assert!(!Access::default().allows_ip(&"10.0.0.2".parse().unwrap()));
+        assert!(Access::default().permits(&"192.168.0.1".parse().unwrap(), &other));
+        assert!(matches!(
+            "permit 10.0.0.1".parse::<Access>(),
+            Err(Error::Line(1, _))
+        ));
+    }
+}
@@ -1,7 +1,16 @@
+use super::MAX_REQUEST_SIZE;
 use super::MAX_TRANSMIT_SIZE;
-use super::PROTOCOL_NAME;
+use super::PROTOCOL_NAME_1;
+use super::PROTOCOL_NAME_2;
+use super::PROTOCOL_NAME_3;
+use super::PROTOCOL_NAME_KAD;
 use super::PROTOCOL_VERSION;
+use super::STATUS_VERSION;
+use super::STATUS_VERSION_MIN;
 use async_trait::async_trait;
+use block::Block;
+use block::Header;
+use block::Missing;
 use futures::prelude::*;
 use libp2p::autonat;
 use libp2p::connection_limits;
@@ -9,15 +18,32 @@ use libp2p::connection_limits::ConnectionLimits;
 use libp2p::core::upgrade::read_length_prefixed;
 use libp2p::core::upgrade::write_length_prefixed;
 use libp2p::core::upgrade::ProtocolName;
+use libp2p::dcutr;
 use libp2p::gossipsub;
 use libp2p::gossipsub::MessageAuthenticity;
 use libp2p::identify;
 use libp2p::identity;
+use libp2p::kad::store::MemoryStore;
+use libp2p::kad::Kademlia;
+use libp2p::kad::KademliaConfig;
+use libp2p::kad::KademliaEvent;
 use libp2p::mdns;
+use libp2p::relay;
 use libp2p::request_response;
 use libp2p::request_response::ProtocolSupport;
+use libp2p::swarm::behaviour::toggle::Toggle;
 use libp2p::swarm::NetworkBehaviour;
+use serde::de;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use stake::Stake;
+use std::borrow::Cow;
+use std::fmt;
 use tokio::io;
+use transaction::Transaction;
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
@@ -30,11 +56,17 @@ pub struct Behaviour {
     pub gossipsub: gossipsub::Behaviour,
     pub autonat: autonat::Behaviour,
     pub request_response: request_response::Behaviour<Codec>,
+    pub kademlia: Kademlia<MemoryStore>,
+    pub relay: Toggle<relay::Behaviour>,
+    pub relay_client: relay::client::Behaviour,
+    pub dcutr: dcutr::Behaviour,
     pub connection_limits: connection_limits::Behaviour,
 }
 impl Behaviour {
     pub async fn new(
         local_key: identity::Keypair,
+        relay_client: relay::client::Behaviour,
+        relay: bool,
         max_established: Option<u32>,
     ) -> Result<Behaviour, Error> {
         let local_public_key = local_key.public();
@@ -57,12 +89,25 @@ impl Behaviour {
         let autonat = autonat::Behaviour::new(local_peer_id, autonat::Config::default());
         let request_response = request_response::Behaviour::new(
             Codec(),
-            std::iter::once((Protocol(), ProtocolSupport::Full)),
+            [
+                (Protocol::V3, ProtocolSupport::Full),
+                (Protocol::V2, ProtocolSupport::Full),
+                (Protocol::V1, ProtocolSupport::Full),
+            ],
             Default::default(),
         );
+        let kademlia = {
+            let mut config = KademliaConfig::default();
+            config.set_protocol_names(vec![Cow::Borrowed(PROTOCOL_NAME_KAD.as_bytes())]);
+            Kademlia::with_config(local_peer_id, MemoryStore::new(local_peer_id), config)
+        };
+        let relay = Toggle::from(
+            relay.then(|| relay::Behaviour::new(local_peer_id, relay::Config::default())),
+        );
+        let dcutr = dcutr::Behaviour::new(local_peer_id);
         let connection_limits = {
             let mut connection_limits = ConnectionLimits::default();
-            connection_limits = connection_limits.with_max_established_per_peer(Some(1));
+            connection_limits = connection_limits.with_max_established_per_peer(Some(2));
             connection_limits = connection_limits.with_max_established(max_established);
             connection_limits::Behaviour::new(connection_limits)
         };
@@ -72,6 +117,10 @@ impl Behaviour {
             gossipsub,
             autonat,
             request_response,
+            kademlia,
+            relay,
+            relay_client,
+            dcutr,
             connection_limits,
         };
         Ok(behaviour)
@@ -84,6 +133,10 @@ pub enum OutEvent {
     Identify(identify::Event),
     Autonat(autonat::Event),
     RequestResponse(request_response::Event<Request, Response>),
+    Kademlia(KademliaEvent),
+    Relay(relay::Event),
+    RelayClient(relay::client::Event),
+    Dcutr(dcutr::Event),
     Void(void::Void),
 }
 impl From<mdns::Event> for OutEvent {
@@ -111,22 +164,162 @@ impl From<request_response::Event<Request, Response>> for OutEvent {
         OutEvent::RequestResponse(v)
     }
 }
+impl From<KademliaEvent> for OutEvent {
+    fn from(v: KademliaEvent) -> OutEvent {
+        OutEvent::Kademlia(v)
+    }
+}
+impl From<relay::Event> for OutEvent {
+    fn from(v: relay::Event) -> OutEvent {
+        OutEvent::Relay(v)
+    }
+}
+impl From<relay::client::Event> for OutEvent {
+    fn from(v: relay::client::Event) -> OutEvent {
+        OutEvent::RelayClient(v)
+    }
+}
+impl From<dcutr::Event> for OutEvent {
+    fn from(v: dcutr::Event) -> OutEvent {
+        OutEvent::Dcutr(v)
+    }
+}
 impl From<void::Void> for OutEvent {
     fn from(v: void::Void) -> OutEvent {
         OutEvent::Void(v)
     }
 }
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Request(pub Vec<u8>);
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Response(pub Vec<u8>);
-#[derive(Debug, Clone)]
-pub struct Protocol();
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Request {
+    Blocks(usize),
+    Headers(usize),
+    Bodies(Vec<[u8; 32]>),
+    Range(usize, usize),
+    Block([u8; 32]),
+    Pending,
+    Peers,
+    Status(Status),
+    BlockItems([u8; 32], Missing),
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Response {
+    Blocks(Vec<Block>),
+    Headers(Vec<Header>),
+    Block(Option<Block>),
+    Pending(Vec<Transaction>, Vec<Stake>),
+    Peers(Vec<PeerRecord>),
+    Status(Status),
+    BlockItems([u8; 32], Vec<Transaction>, Vec<Stake>),
+    Refused(Refusal),
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub address: String,
+    pub timestamp: u32,
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Status {
+    pub version: u32,
+    pub network: u8,
+    pub genesis: [u8; 32],
+    pub height: usize,
+    pub hash: [u8; 32],
+    pub timestamp: i64,
+    pub compact_blocks: bool,
+}
+impl Status {
+    pub fn compatible(&self, status: &Status) -> bool {
+        let versions = STATUS_VERSION_MIN..=STATUS_VERSION;
+        if !versions.contains(&self.version) || !versions.contains(&status.version) {
+            return false;
+        }
+        if self.network != status.network {
+            return false;
+        }
+        self.genesis == [0; 32] || status.genesis == [0; 32] || self.genesis == status.genesis
+    }
+    pub fn timestamp_millis(&self) -> Option<i64> {
+        (self.version >= 4).then_some(self.timestamp)
+    }
+}
+impl<'de> Deserialize<'de> for Status {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Status, D::Error> {
+        deserializer.deserialize_tuple(7, StatusVisitor)
+    }
+}
+struct StatusVisitor;
+impl<'de> Visitor<'de> for StatusVisitor {
+    type Value = Status;
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("struct Status")
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Status, A::Error> {
+        let mut status = Status {
+            version: element(&mut seq)?,
+            network: element(&mut seq)?,
+            genesis: element(&mut seq)?,
+            height: element(&mut seq)?,
+            hash: element(&mut seq)?,
+            ..Default::default()
+        };
+        if status.version >= 4 {
+            status.timestamp = element(&mut seq)?;
+        }
+        if status.version >= 5 {
+            status.compact_blocks = element(&mut seq)?;
+        }
+        Ok(status)
+    }
+}
+fn element<'de, A: SeqAccess<'de>, T: Deserialize<'de>>(seq: &mut A) -> Result<T, A::Error> {
+    seq.next_element()?
+        .ok_or_else(|| de::Error::custom("missing status field"))
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Refusal {
+    Ratelimited,
+    TooLarge,
+    Unavailable,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    V1,
+    V2,
+    V3,
+}
+impl Protocol {
+    pub fn supports(&self, request: &Request) -> bool {
+        match self {
+            Protocol::V1 => matches!(request, Request::Blocks(_) | Request::Headers(_)),
+            Protocol::V2 => matches!(
+                request,
+                Request::Blocks(_) | Request::Headers(_) | Request::Bodies(_)
+            ),
+            Protocol::V3 => true,
+        }
+    }
+}
 impl ProtocolName for Protocol {
     fn protocol_name(&self) -> &[u8] {
-        PROTOCOL_NAME.as_bytes()
+        match self {
+            Protocol::V1 => PROTOCOL_NAME_1.as_bytes(),
+            Protocol::V2 => PROTOCOL_NAME_2.as_bytes(),
+            Protocol::V3 => PROTOCOL_NAME_3.as_bytes(),
+        }
     }
 }
+fn invalid_data(e: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+fn unsupported(protocol: &Protocol) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "not supported by {}",
+            String::from_utf8_lossy(protocol.protocol_name())
+        ),
+    )
+}
 #[derive(Clone)]
 pub struct Codec();
 #[async_trait]
@@ -136,38 +329,79 @@ impl request_response::Codec for Codec {
     type Response = Response;
     async fn read_request<T: AsyncRead + Unpin + Send>(
         &mut self,
-        _: &Protocol,
+        protocol: &Protocol,
         io: &mut T,
     ) -> io::Result<Self::Request> {
-        let vec = read_length_prefixed(io, 8).await?;
-        let request = Request(vec);
-        Ok(request)
+        match protocol {
+            Protocol::V1 => {
+                let vec = read_length_prefixed(io, 8).await?;
+                let height = bincode::deserialize(&vec).map_err(invalid_data)?;
+                Ok(Request::Blocks(height))
+            }
+            Protocol::V2 | Protocol::V3 => {
+                let vec = read_length_prefixed(io, MAX_REQUEST_SIZE).await?;
+                bincode::deserialize(&vec).map_err(invalid_data)
+            }
+        }
     }
     async fn read_response<T: AsyncRead + Unpin + Send>(
         &mut self,
-        _: &Protocol,
+        protocol: &Protocol,
         io: &mut T,
     ) -> io::Result<Self::Response> {
         let vec = read_length_prefixed(io, MAX_TRANSMIT_SIZE).await?;
-        let response = Response(vec);
-        Ok(response)
+        match protocol {
+            Protocol::V1 => {
+                let blocks = bincode::deserialize(&vec).map_err(invalid_data)?;
+                Ok(Response::Blocks(blocks))
+            }
+            Protocol::V2 | Protocol::V3 => bincode::deserialize(&vec).map_err(invalid_data),
+        }
     }
     async fn write_request<T: AsyncWrite + Unpin + Send>(
         &mut self,
-        _: &Protocol,
+        protocol: &Protocol,
         io: &mut T,
-        Request(vec): Request,
+        request: Request,
     ) -> io::Result<()> {
+        if !protocol.supports(&request) {
+            return Err(unsupported(protocol));
+        }
+        let vec = match (protocol, request) {
+            (Protocol::V1, Request::Blocks(height)) | (Protocol::V1, Request::Headers(height)) => {
+                bincode::serialize(&height).map_err(invalid_data)?
+            }
+            (_, request) => bincode::serialize(&request).map_err(invalid_data)?,
+        };
         write_length_prefixed(io, vec).await?;
         io.close().await?;
         Ok(())
     }
     async fn write_response<T: AsyncWrite + Unpin + Send>(
         &mut self,
-        _: &Protocol,
+        protocol: &Protocol,
         io: &mut T,
-        Response(vec): Response,
+        response: Response,
     ) -> io::Result<()> {
+        let vec = match (protocol, response) {
+            (Protocol::V3, response) => bincode::serialize(&response).map_err(invalid_data)?,
+            (Protocol::V1, Response::Blocks(blocks)) => {
+                bincode::serialize(&blocks).map_err(invalid_data)?
+            }
+            (Protocol::V1, Response::Refused(_)) => {
+                bincode::serialize(&Vec::<Block>::new()).map_err(invalid_data)?
+            }
+            (Protocol::V2, Response::Blocks(blocks)) => {
+                bincode::serialize(&Response::Blocks(blocks)).map_err(invalid_data)?
+            }
+            (Protocol::V2, Response::Headers(headers)) => {
+                bincode::serialize(&Response::Headers(headers)).map_err(invalid_data)?
+            }
+            (Protocol::V2, Response::Refused(_)) => {
+                bincode::serialize(&Response::Blocks(vec![])).map_err(invalid_data)?
+            }
+            _ => return Err(unsupported(protocol)),
+        };
         write_length_prefixed(io, vec).await?;
         io.close().await?;
         Ok(())
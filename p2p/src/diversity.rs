@@ -0,0 +1,235 @@
+use super::P2P_DIVERSITY_IPV4_16;
+use super::P2P_DIVERSITY_IPV4_24;
+use super::P2P_DIVERSITY_IPV6_32;
+use super::P2P_DIVERSITY_IPV6_48;
+use super::P2P_DIVERSITY_OUTBOUND_RESERVED;
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::net::IpAddr;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Subnet {
+    Ipv4_16([u8; 2]),
+    Ipv4_24([u8; 3]),
+    Ipv6_32([u8; 4]),
+    Ipv6_48([u8; 6]),
+}
+impl Subnet {
+    pub fn of(ip_addr: &IpAddr) -> [Subnet; 2] {
+        match ip_addr {
+            IpAddr::V4(ip) => {
+                let o = ip.octets();
+                [
+                    Subnet::Ipv4_16([o[0], o[1]]),
+                    Subnet::Ipv4_24([o[0], o[1], o[2]]),
+                ]
+            }
+            IpAddr::V6(ip) => {
+                let o = ip.octets();
+                [
+                    Subnet::Ipv6_32([o[0], o[1], o[2], o[3]]),
+                    Subnet::Ipv6_48([o[0], o[1], o[2], o[3], o[4], o[5]]),
+                ]
+            }
+        }
+    }
+    pub fn limit(&self, limits: &Limits) -> usize {
+        match self {
+            Subnet::Ipv4_16(_) => limits.ipv4_16,
+            Subnet::Ipv4_24(_) => limits.ipv4_24,
+            Subnet::Ipv6_32(_) => limits.ipv6_32,
+            Subnet::Ipv6_48(_) => limits.ipv6_48,
+        }
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_established: Option<u32>,
+    pub ipv4_16: usize,
+    pub ipv4_24: usize,
+    pub ipv6_32: usize,
+    pub ipv6_48: usize,
+    pub outbound_reserved: usize,
+}
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_established: None,
+            ipv4_16: P2P_DIVERSITY_IPV4_16,
+            ipv4_24: P2P_DIVERSITY_IPV4_24,
+            ipv6_32: P2P_DIVERSITY_IPV6_32,
+            ipv6_48: P2P_DIVERSITY_IPV6_48,
+            outbound_reserved: P2P_DIVERSITY_OUTBOUND_RESERVED,
+        }
+    }
+}
+impl Limits {
+    pub fn max_inbound(&self) -> Option<usize> {
+        self.max_established
+            .map(|x| (x as usize).saturating_sub(self.outbound_reserved))
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reject {
+    Subnet(Subnet),
+    InboundFull,
+}
+#[derive(Debug, Default)]
+pub struct Diversity {
+    pub limits: Limits,
+    peers: HashMap<PeerId, (IpAddr, Direction)>,
+}
+impl Diversity {
+    pub fn new(limits: Limits) -> Diversity {
+        Diversity {
+            limits,
+            peers: HashMap::new(),
+        }
+    }
+    pub fn check(&self, ip_addr: &IpAddr, direction: Direction) -> Result<(), Reject> {
+        let subnets = match multiaddr::is_global(ip_addr) {
+            true => Subnet::of(ip_addr).to_vec(),
+            false => vec![],
+        };
+        for subnet in subnets {
+            if self.count(direction, |x| Subnet::of(x).contains(&subnet))
+                >= subnet.limit(&self.limits)
+            {
+                return Err(Reject::Subnet(subnet));
+            }
+        }
+        if direction == Direction::Inbound {
+            if let Some(max_inbound) = self.limits.max_inbound() {
+                if self.count(Direction::Inbound, |_| true) >= max_inbound {
+                    return Err(Reject::InboundFull);
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn insert(&mut self, peer_id: PeerId, ip_addr: IpAddr, direction: Direction) {
+        self.peers.insert(peer_id, (ip_addr, direction));
+    }
+    pub fn direction(&self, peer_id: &PeerId) -> Option<Direction> {
+        self.peers.get(peer_id).map(|(_, direction)| *direction)
+    }
+    pub fn remove(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+    pub fn evict(&self, score: impl Fn(&IpAddr) -> i64) -> Option<(PeerId, IpAddr, i64)> {
+        self.peers
+            .iter()
+            .filter(|(_, (_, direction))| *direction == Direction::Inbound)
+            .map(|(peer_id, (ip_addr, _))| {
+                let crowd = self.count(Direction::Inbound, |x| {
+                    Subnet::of(x)[0] == Subnet::of(ip_addr)[0]
+                });
+                (*peer_id, *ip_addr, score(ip_addr), crowd)
+            })
+            .min_by(|a, b| a.2.cmp(&b.2).then(b.3.cmp(&a.3)))
+            .map(|(peer_id, ip_addr, score, _)| (peer_id, ip_addr, score))
+    }
+    fn count(&self, direction: Direction, f: impl Fn(&IpAddr) -> bool) -> usize {
+        self.peers
+            .values()
+            .filter(|(ip_addr, x)| *x == direction && f(ip_addr))
+            .count()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_subnet_limits() {
+        let mut diversity = Diversity::new(Limits {
+            ipv4_16: 3,
+            ipv4_24: 2,
+            ..Default::default()
+        });
+        for i in 0..2 {
+            let ip_addr = IpAddr::from([11, 0, 0, i]);
+            assert_eq!(diversity.check(&ip_addr, Direction::Inbound), Ok(()));
+            diversity.insert(PeerId::random(), ip_addr, Direction::Inbound);
+        }
+        assert_eq!(
+            diversity.check(&IpAddr::from([11, 0, 0, 2]), Direction::Inbound),
+            Err(Reject::Subnet(Subnet::Ipv4_24([11, 0, 0])))
+        );
+        assert_eq!(
+            diversity.check(&IpAddr::from([11, 0, 0, 2]), Direction::Outbound),
+            Ok(())
+        );
+        diversity.insert(
+            PeerId::random(),
+            IpAddr::from([11, 0, 1, 0]),
+            Direction::Inbound,
+        );
+        assert_eq!(
+            diversity.check(&IpAddr::from([11, 0, 2, 0]), Direction::Inbound),
+            Err(Reject::Subnet(Subnet::Ipv4_16([11, 0])))
+        );
+        assert_eq!(
+            diversity.check(&IpAddr::from([11, 1, 0, 0]), Direction::Inbound),
+            Ok(())
+        );
+        for ip_addr in [[127, 0, 0, 1], [10, 0, 0, 1], [192, 168, 0, 1]] {
+            for _ in 0..4 {
+                diversity.insert(PeerId::random(), IpAddr::from(ip_addr), Direction::Inbound);
+            }
+            assert_eq!(
+                diversity.check(&IpAddr::from(ip_addr), Direction::Inbound),
+                Ok(())
+            );
+        }
+    }
+    #[test]
+    fn test_outbound_reserved() {
+        let mut diversity = Diversity::new(Limits {
+            max_established: Some(3),
+            outbound_reserved: 1,
+            ..Default::default()
+        });
+        diversity.insert(
+            PeerId::random(),
+            IpAddr::from([10, 0, 0, 0]),
+            Direction::Inbound,
+        );
+        diversity.insert(
+            PeerId::random(),
+            IpAddr::from([10, 1, 0, 0]),
+            Direction::Inbound,
+        );
+        assert_eq!(
+            diversity.check(&IpAddr::from([10, 2, 0, 0]), Direction::Inbound),
+            Err(Reject::InboundFull)
+        );
+        assert_eq!(
+            diversity.check(&IpAddr::from([10, 2, 0, 0]), Direction::Outbound),
+            Ok(())
+        );
+    }
+    #[test]
+    fn test_evict() {
+        let mut diversity = Diversity::new(Limits::default());
+        let a = PeerId::random();
+        let b = PeerId::random();
+        let c = PeerId::random();
+        diversity.insert(a, IpAddr::from([10, 0, 0, 0]), Direction::Inbound);
+        diversity.insert(b, IpAddr::from([10, 0, 0, 1]), Direction::Inbound);
+        diversity.insert(c, IpAddr::from([10, 1, 0, 0]), Direction::Inbound);
+        diversity.insert(
+            PeerId::random(),
+            IpAddr::from([10, 2, 0, 0]),
+            Direction::Outbound,
+        );
+        let evicted = diversity.evict(|ip_addr| match ip_addr {
+            IpAddr::V4(ip) if ip.octets()[3] == 1 => 10,
+            _ => 0,
+        });
+        assert_eq!(evicted.map(|x| x.0), Some(a));
+    }
+}
@@ -1,61 +1,142 @@
+pub mod access;
 pub mod behaviour;
+pub mod diversity;
+pub mod metrics;
 pub mod ratelimit;
+pub mod topic;
+use access::Access;
 use behaviour::Behaviour;
+use behaviour::Request;
+use behaviour::Response;
+use behaviour::Status;
+use clock::Clock;
+use clock::NetworkTime;
+use clock::SharedClock;
+use diversity::Diversity;
+use diversity::Limits;
+use futures::future::Either;
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::OptionalTransport;
+use libp2p::core::transport::OrTransport;
 use libp2p::core::upgrade;
-use libp2p::gossipsub::IdentTopic;
 use libp2p::gossipsub::PublishError;
 use libp2p::gossipsub::SubscriptionError;
 use libp2p::gossipsub::TopicHash;
 use libp2p::identity;
+use libp2p::multiaddr::Multiaddr;
+use libp2p::multiaddr::Protocol;
 use libp2p::noise;
+use libp2p::pnet::PnetConfig;
+use libp2p::pnet::PreSharedKey;
+use libp2p::quic;
+use libp2p::relay;
+use libp2p::request_response::RequestId;
+use libp2p::request_response::ResponseChannel;
 use libp2p::swarm::SwarmBuilder;
 use libp2p::tcp;
+use libp2p::websocket;
 use libp2p::yamux;
 use libp2p::PeerId;
 use libp2p::Swarm;
 use libp2p::Transport;
+use metrics::Metrics;
 use ratelimit::Ratelimit;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::net::IpAddr;
 use std::time::Duration;
+use sync::scheduler::Scheduler;
+use topic::Topic;
 use tracing::log::warn;
 pub const MAX_TRANSMIT_SIZE: usize = 100_000;
+pub const MAX_RESPONSE_SIZE: usize = MAX_TRANSMIT_SIZE - 16;
+pub const MAX_REQUEST_SIZE: usize = 10_000;
 pub const PROTOCOL_VERSION: &str = "tofuri/1.0.0";
-pub const PROTOCOL_NAME: &str = "/sync/1";
+pub const PROTOCOL_NAME_1: &str = "/sync/1";
+pub const PROTOCOL_NAME_2: &str = "/sync/2";
+pub const PROTOCOL_NAME_3: &str = "/sync/3";
+pub const PROTOCOL_NAME_KAD: &str = "/tofuri/kad/1";
+pub const PROTOCOL_NAME_RELAY_HOP: &str = "/libp2p/circuit/relay/0.2.0/hop";
+pub const STATUS_VERSION: u32 = 5;
+pub const STATUS_VERSION_MIN: u32 = 3;
+pub const NETWORK_MAINNET: u8 = 0;
+pub const NETWORK_TESTNET: u8 = 1;
+pub const P2P_SYNC_TIMEOUT: u32 = 30;
 pub const P2P_RATELIMIT_REQUEST_TIMEOUT: u32 = 3600;
 pub const P2P_RATELIMIT_RESPONSE_TIMEOUT: u32 = 3600;
 pub const P2P_RATELIMIT_REQUEST: usize = 60 + 1;
 pub const P2P_RATELIMIT_RESPONSE: usize = 60 + 1;
 pub const P2P_RATELIMIT_GOSSIPSUB_MESSAGE_BLOCK: usize = 1 + 1;
+pub const P2P_RATELIMIT_GOSSIPSUB_MESSAGE_COMPACT_BLOCK: usize = 1 + 1;
 pub const P2P_RATELIMIT_GOSSIPSUB_MESSAGE_TRANSACTION: usize = 60 * 100;
 pub const P2P_RATELIMIT_GOSSIPSUB_MESSAGE_STAKE: usize = 60 * 100;
-pub const P2P_RATELIMIT_GOSSIPSUB_MESSAGE_PEERS: usize = 1 + 1;
+pub const P2P_RATELIMIT_MAX_LEN: usize = 10_000;
+pub const P2P_RATELIMIT_COST: u64 = 60_000;
+pub const P2P_DIVERSITY_IPV4_16: usize = 4;
+pub const P2P_DIVERSITY_IPV4_24: usize = 2;
+pub const P2P_DIVERSITY_IPV6_32: usize = 4;
+pub const P2P_DIVERSITY_IPV6_48: usize = 2;
+pub const P2P_DIVERSITY_OUTBOUND_RESERVED: usize = 8;
 #[derive(Debug)]
 pub enum Error {
     PublishError(PublishError),
     Behaviour(behaviour::Error),
     SubscriptionError(SubscriptionError),
 }
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Transports {
+    pub quic: bool,
+    pub websocket: bool,
+    pub relay: bool,
+    pub psk: Option<PreSharedKey>,
+}
 pub struct P2P {
     pub swarm: Swarm<Behaviour>,
     pub connections: HashMap<PeerId, IpAddr>,
-    pub connections_unknown: HashSet<IpAddr>,
-    pub connections_known: HashSet<IpAddr>,
+    pub addresses: HashMap<PeerId, Multiaddr>,
     pub ratelimit: Ratelimit,
+    pub scheduler: Scheduler<PeerId>,
+    pub statuses: HashMap<PeerId, Status>,
+    pub diversity: Diversity,
+    pub access: Access,
+    pub metrics: Metrics,
+    pub relays: HashSet<PeerId>,
+    pub reservations: HashSet<PeerId>,
+    pub network_time: NetworkTime<PeerId>,
+    pub clock: SharedClock,
+    pub network: u8,
 }
 impl P2P {
     pub async fn new(
-        max_established: Option<u32>,
+        local_key: identity::Keypair,
+        transports: Transports,
+        network: u8,
+        limits: Limits,
+        clock: SharedClock,
         timeout: u64,
-        connections_known: HashSet<IpAddr>,
     ) -> Result<P2P, Error> {
         let p2p = P2P {
-            swarm: swarm(max_established, timeout).await?,
+            swarm: swarm(
+                local_key,
+                transports,
+                network,
+                limits.max_established,
+                timeout,
+            )
+            .await?,
             connections: HashMap::new(),
-            connections_unknown: HashSet::new(),
-            connections_known,
-            ratelimit: Ratelimit::default(),
+            addresses: HashMap::new(),
+            ratelimit: Ratelimit::new(clock.clone()),
+            scheduler: Scheduler::new(P2P_SYNC_TIMEOUT),
+            statuses: HashMap::new(),
+            diversity: Diversity::new(limits),
+            access: Access::default(),
+            metrics: Metrics::new(timeout),
+            relays: HashSet::new(),
+            reservations: HashSet::new(),
+            network_time: NetworkTime::default(),
+            clock,
+            network,
         };
         Ok(p2p)
     }
@@ -73,50 +154,297 @@ impl P2P {
         }
         vec
     }
-    fn gossipsub_has_mesh_peers(&self, topic: &str) -> bool {
+    pub fn topic(&self, topic_hash: &TopicHash) -> Option<Topic> {
+        Topic::ALL.into_iter().find(|x| {
+            x.ident_topics(self.network)
+                .iter()
+                .any(|x| &x.hash() == topic_hash)
+        })
+    }
+    pub fn compact_blocks_supported(&self) -> bool {
+        self.swarm.connected_peers().all(|x| {
+            self.statuses
+                .get(x)
+                .map(|x| x.compact_blocks)
+                .unwrap_or_default()
+        })
+    }
+    fn gossipsub_has_mesh_peers(&self, topic_hash: &TopicHash) -> bool {
         self.swarm
             .behaviour()
             .gossipsub
-            .mesh_peers(&TopicHash::from_raw(topic))
+            .mesh_peers(topic_hash)
             .count()
             != 0
     }
-    pub fn gossipsub_publish(&mut self, topic: &str, data: Vec<u8>) -> Result<(), Error> {
-        if !self.gossipsub_has_mesh_peers(topic) {
-            return Ok(());
+    pub fn gossipsub_publish(&mut self, topic: Topic, data: Vec<u8>) -> Result<(), Error> {
+        let bytes = data.len();
+        for ident_topic in topic.ident_topics(self.network) {
+            if !self.gossipsub_has_mesh_peers(&ident_topic.hash()) {
+                continue;
+            }
+            self.swarm
+                .behaviour_mut()
+                .gossipsub
+                .publish(ident_topic.clone(), data.clone())
+                .map_err(Error::PublishError)?;
+            let peer_ids: Vec<PeerId> = self
+                .swarm
+                .behaviour()
+                .gossipsub
+                .mesh_peers(&ident_topic.hash())
+                .cloned()
+                .collect();
+            for peer_id in peer_ids {
+                self.metrics.outbound(&peer_id, Some(topic), bytes);
+            }
+        }
+        Ok(())
+    }
+    pub fn relay_listen(&mut self) -> bool {
+        if !self.reservations.is_empty() {
+            return false;
         }
+        let (peer_id, multiaddr) = match self
+            .relays
+            .iter()
+            .find_map(|x| self.addresses.get(x).map(|y| (*x, y.clone())))
+        {
+            Some(x) => x,
+            None => return false,
+        };
+        let multiaddr = multiaddr
+            .with(Protocol::P2p(peer_id.into()))
+            .with(Protocol::P2pCircuit);
+        match self.swarm.listen_on(multiaddr) {
+            Ok(_) => {
+                self.reservations.insert(peer_id);
+                true
+            }
+            Err(e) => {
+                warn!("Failed to listen on relay {}: {}", peer_id, e);
+                false
+            }
+        }
+    }
+    pub fn send_request(&mut self, peer_id: &PeerId, request: Request) -> RequestId {
+        let bytes = bincode::serialized_size(&request).unwrap_or_default() as usize;
+        let request_id = self
+            .swarm
+            .behaviour_mut()
+            .request_response
+            .send_request(peer_id, request);
+        self.metrics.outbound(peer_id, None, bytes);
+        self.metrics
+            .request_sent(request_id, self.clock.timestamp_millis() as u64);
+        request_id
+    }
+    pub fn send_response(
+        &mut self,
+        peer_id: &PeerId,
+        channel: ResponseChannel<Response>,
+        response: Response,
+    ) -> Result<(), Response> {
+        let bytes = bincode::serialized_size(&response).unwrap_or_default() as usize;
         self.swarm
             .behaviour_mut()
-            .gossipsub
-            .publish(IdentTopic::new(topic), data)
-            .map_err(Error::PublishError)?;
+            .request_response
+            .send_response(channel, response)?;
+        self.metrics.outbound(peer_id, None, bytes);
         Ok(())
     }
 }
-async fn swarm(max_established: Option<u32>, timeout: u64) -> Result<Swarm<Behaviour>, Error> {
-    let local_key = identity::Keypair::generate_ed25519();
+async fn swarm(
+    local_key: identity::Keypair,
+    transports: Transports,
+    network: u8,
+    max_established: Option<u32>,
+    timeout: u64,
+) -> Result<Swarm<Behaviour>, Error> {
     let local_peer_id = PeerId::from(local_key.public());
-    let transport = tcp::tokio::Transport::new(tcp::Config::default().nodelay(true))
+    let (relay_transport, relay_client) = relay::client::new(local_peer_id);
+    let psk = transports.psk;
+    let tcp_config = tcp::Config::default().nodelay(true);
+    let websocket = match transports.websocket {
+        true => OptionalTransport::some(websocket::WsConfig::new(tcp::tokio::Transport::new(
+            tcp_config.clone(),
+        ))),
+        false => OptionalTransport::none(),
+    };
+    let quic = match transports.quic && psk.is_none() {
+        true => OptionalTransport::some(quic::tokio::Transport::new(quic::Config::new(&local_key))),
+        false => OptionalTransport::none(),
+    };
+    let transport = OrTransport::new(websocket, tcp::tokio::Transport::new(tcp_config));
+    let transport = OrTransport::new(relay_transport, transport)
+        .and_then(move |socket, _| async move {
+            match psk {
+                Some(psk) => PnetConfig::new(psk)
+                    .handshake(socket)
+                    .await
+                    .map(Either::Left),
+                None => Ok(Either::Right(socket)),
+            }
+        })
         .upgrade(upgrade::Version::V1)
         .authenticate(noise::Config::new(&local_key).unwrap())
         .multiplex(yamux::Config::default())
-        .timeout(Duration::from_millis(timeout))
+        .timeout(Duration::from_millis(timeout));
+    let transport = OrTransport::new(quic, transport)
+        .map(|either, _| match either {
+            Either::Left((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+            Either::Right((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+        })
         .boxed();
-    let mut behaviour = Behaviour::new(local_key, max_established)
+    let mut behaviour = Behaviour::new(local_key, relay_client, transports.relay, max_established)
         .await
         .map_err(Error::Behaviour)?;
-    let topics = [
-        IdentTopic::new("block"),
-        IdentTopic::new("stake"),
-        IdentTopic::new("transaction"),
-        IdentTopic::new("peers"),
-    ];
-    for topic in topics.iter() {
+    for ident_topic in Topic::ALL.iter().flat_map(|x| x.ident_topics(network)) {
         behaviour
             .gossipsub
-            .subscribe(topic)
+            .subscribe(&ident_topic)
             .map_err(Error::SubscriptionError)?;
     }
     let swarm = SwarmBuilder::with_tokio_executor(transport, behaviour, local_peer_id).build();
     Ok(swarm)
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use behaviour::OutEvent;
+    use futures::StreamExt;
+    use libp2p::kad::KademliaEvent;
+    use libp2p::swarm::AddressScore;
+    use libp2p::swarm::SwarmEvent;
+    use libp2p::Multiaddr;
+    async fn node(transports: Transports) -> (P2P, Multiaddr) {
+        let mut p2p = P2P::new(
+            identity::Keypair::generate_ed25519(),
+            transports,
+            NETWORK_TESTNET,
+            Limits::default(),
+            SharedClock::default(),
+            10000,
+        )
+        .await
+        .unwrap();
+        p2p.swarm
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = p2p.swarm.select_next_some().await {
+                return (p2p, address);
+            }
+        }
+    }
+    #[tokio::test]
+    async fn test_kademlia() {
+        let (mut a, a_multiaddr) = node(Transports::default()).await;
+        let (mut b, b_multiaddr) = node(Transports::default()).await;
+        let (mut c, _) = node(Transports::default()).await;
+        let a_peer_id = *a.swarm.local_peer_id();
+        let b_peer_id = *b.swarm.local_peer_id();
+        a.swarm
+            .behaviour_mut()
+            .kademlia
+            .add_address(&b_peer_id, b_multiaddr);
+        c.swarm
+            .behaviour_mut()
+            .kademlia
+            .add_address(&a_peer_id, a_multiaddr);
+        c.swarm.behaviour_mut().kademlia.bootstrap().unwrap();
+        let timeout = tokio::time::sleep(Duration::from_secs(30));
+        tokio::pin!(timeout);
+        loop {
+            tokio::select! {
+                _ = a.swarm.select_next_some() => {}
+                _ = b.swarm.select_next_some() => {}
+                event = c.swarm.select_next_some() => {
+                    if let SwarmEvent::Behaviour(OutEvent::Kademlia(KademliaEvent::RoutingUpdated { peer, .. })) = event {
+                        if peer == b_peer_id {
+                            break;
+                        }
+                    }
+                }
+                _ = &mut timeout => panic!("c did not discover b"),
+            }
+        }
+    }
+    #[tokio::test]
+    async fn test_relay() {
+        let (mut relay, relay_multiaddr) = node(Transports {
+            relay: true,
+            ..Default::default()
+        })
+        .await;
+        let (mut a, _) = node(Transports::default()).await;
+        let (mut b, _) = node(Transports::default()).await;
+        let relay_peer_id = *relay.swarm.local_peer_id();
+        let a_peer_id = *a.swarm.local_peer_id();
+        relay
+            .swarm
+            .add_external_address(relay_multiaddr.clone(), AddressScore::Infinite);
+        let circuit = relay_multiaddr
+            .with(Protocol::P2p(relay_peer_id.into()))
+            .with(Protocol::P2pCircuit);
+        a.swarm.listen_on(circuit.clone()).unwrap();
+        let timeout = tokio::time::sleep(Duration::from_secs(30));
+        tokio::pin!(timeout);
+        loop {
+            tokio::select! {
+                _ = relay.swarm.select_next_some() => {}
+                event = a.swarm.select_next_some() => {
+                    if let SwarmEvent::Behaviour(OutEvent::RelayClient(relay::client::Event::ReservationReqAccepted { .. })) = event {
+                        b.swarm.dial(circuit.clone().with(Protocol::P2p(a_peer_id.into()))).unwrap();
+                    }
+                }
+                event = b.swarm.select_next_some() => {
+                    if let SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } = event {
+                        if peer_id == a_peer_id && endpoint.is_relayed() {
+                            break;
+                        }
+                    }
+                }
+                _ = &mut timeout => panic!("b did not connect to a through the relay"),
+            }
+        }
+    }
+    #[test]
+    fn test_status_legacy() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct StatusV3 {
+            version: u32,
+            network: u8,
+            genesis: [u8; 32],
+            height: usize,
+            hash: [u8; 32],
+        }
+        let status = Status {
+            version: STATUS_VERSION,
+            timestamp: 1,
+            compact_blocks: true,
+            ..Default::default()
+        };
+        let bytes = bincode::serialize(&status).unwrap();
+        let legacy: StatusV3 = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(legacy.version, STATUS_VERSION);
+        assert_eq!(bincode::deserialize::<Status>(&bytes).unwrap(), status);
+        let legacy = StatusV3 {
+            version: 3,
+            network: 0,
+            genesis: [0; 32],
+            height: 1,
+            hash: [1; 32],
+        };
+        let decoded: Status = bincode::deserialize(&bincode::serialize(&legacy).unwrap()).unwrap();
+        assert_eq!(decoded.height, 1);
+        assert_eq!(decoded.timestamp_millis(), None);
+        assert!(!decoded.compact_blocks);
+        assert!(status.compatible(&decoded));
+        let future = Status {
+            version: STATUS_VERSION + 1,
+            ..Default::default()
+        };
+        assert!(!status.compatible(&future));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::topic::Topic;
+use libp2p::request_response::RequestId;
+use libp2p::PeerId;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counters {
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub rejected: u64,
+    pub ratelimited: u64,
+}
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Latency {
+    pub count: u64,
+    pub total: u64,
+    pub max: u64,
+    pub failures: u64,
+}
+impl Latency {
+    pub fn mean(&self) -> u64 {
+        self.total.checked_div(self.count).unwrap_or_default()
+    }
+}
+#[derive(Debug)]
+pub struct Metrics {
+    pub peers: HashMap<PeerId, Counters>,
+    pub topics: HashMap<Topic, Counters>,
+    pub sync: Counters,
+    pub latency: Latency,
+    pending: HashMap<RequestId, u64>,
+    timeout: u64,
+}
+impl Metrics {
+    pub fn new(timeout: u64) -> Metrics {
+        Metrics {
+            peers: HashMap::new(),
+            topics: HashMap::new(),
+            sync: Counters::default(),
+            latency: Latency::default(),
+            pending: HashMap::new(),
+            timeout,
+        }
+    }
+    pub fn inbound(&mut self, peer_id: &PeerId, topic: Option<Topic>, bytes: usize) {
+        self.apply(peer_id, topic, |x| {
+            x.messages_in += 1;
+            x.bytes_in += bytes as u64;
+        });
+    }
+    pub fn outbound(&mut self, peer_id: &PeerId, topic: Option<Topic>, bytes: usize) {
+        self.apply(peer_id, topic, |x| {
+            x.messages_out += 1;
+            x.bytes_out += bytes as u64;
+        });
+    }
+    pub fn rejected(&mut self, peer_id: &PeerId, topic: Option<Topic>) {
+        self.apply(peer_id, topic, |x| x.rejected += 1);
+    }
+    pub fn ratelimited(&mut self, peer_id: &PeerId, topic: Option<Topic>) {
+        self.apply(peer_id, topic, |x| x.ratelimited += 1);
+    }
+    pub fn request_sent(&mut self, request_id: RequestId, millis: u64) {
+        let timeout = self.timeout;
+        let latency = &mut self.latency;
+        self.pending.retain(|_, sent| {
+            if millis.saturating_sub(*sent) <= timeout {
+                return true;
+            }
+            latency.failures += 1;
+            false
+        });
+        self.pending.insert(request_id, millis);
+    }
+    pub fn response_received(&mut self, request_id: &RequestId, millis: u64) {
+        if let Some(sent) = self.pending.remove(request_id) {
+            let elapsed = millis.saturating_sub(sent);
+            self.latency.count += 1;
+            self.latency.total += elapsed;
+            self.latency.max = self.latency.max.max(elapsed);
+        }
+    }
+    pub fn request_failed(&mut self, request_id: &RequestId) {
+        if self.pending.remove(request_id).is_some() {
+            self.latency.failures += 1;
+        }
+    }
+    pub fn remove(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+    fn apply(&mut self, peer_id: &PeerId, topic: Option<Topic>, f: impl Fn(&mut Counters)) {
+        f(self.peers.entry(*peer_id).or_default());
+        match topic {
+            Some(topic) => f(self.topics.entry(topic).or_default()),
+            None => f(&mut self.sync),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_counters() {
+        let mut metrics = Metrics::new(10_000);
+        let peer_id = PeerId::random();
+        metrics.inbound(&peer_id, Some(Topic::Block), 100);
+        metrics.inbound(&peer_id, None, 10);
+        metrics.outbound(&peer_id, Some(Topic::Block), 50);
+        metrics.rejected(&peer_id, Some(Topic::Block));
+        metrics.ratelimited(&peer_id, None);
+        let peer = metrics.peers[&peer_id];
+        assert_eq!(peer.messages_in, 2);
+        assert_eq!(peer.bytes_in, 110);
+        assert_eq!(peer.bytes_out, 50);
+        assert_eq!(peer.rejected, 1);
+        assert_eq!(peer.ratelimited, 1);
+        let block = metrics.topics[&Topic::Block];
+        assert_eq!(block.messages_in, 1);
+        assert_eq!(block.ratelimited, 0);
+        assert_eq!(metrics.sync.bytes_in, 10);
+        assert_eq!(metrics.sync.ratelimited, 1);
+        metrics.remove(&peer_id);
+        assert!(metrics.peers.is_empty());
+    }
+    #[test]
+    fn test_latency() {
+        let mut latency = Latency::default();
+        assert_eq!(latency.mean(), 0);
+        latency.count = 2;
+        latency.total = 300;
+        assert_eq!(latency.mean(), 150);
+    }
+}
@@ -1,96 +1,265 @@
+use super::P2P_RATELIMIT_COST;
 use super::P2P_RATELIMIT_GOSSIPSUB_MESSAGE_BLOCK;
-use super::P2P_RATELIMIT_GOSSIPSUB_MESSAGE_PEERS;
+use super::P2P_RATELIMIT_GOSSIPSUB_MESSAGE_COMPACT_BLOCK;
 use super::P2P_RATELIMIT_GOSSIPSUB_MESSAGE_STAKE;
 use super::P2P_RATELIMIT_GOSSIPSUB_MESSAGE_TRANSACTION;
+use super::P2P_RATELIMIT_MAX_LEN;
 use super::P2P_RATELIMIT_REQUEST;
 use super::P2P_RATELIMIT_REQUEST_TIMEOUT;
 use super::P2P_RATELIMIT_RESPONSE;
 use super::P2P_RATELIMIT_RESPONSE_TIMEOUT;
-use chrono::Utc;
+use clock::Clock;
+use clock::SharedClock;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::net::IpAddr;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Endpoint {
     Request,
     Response,
     GossipsubMessageBlock,
+    GossipsubMessageCompactBlock,
     GossipsubMessageTransaction,
     GossipsubMessageStake,
-    GossipsubMessagePeers,
 }
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Ratelimit {
-    pub counter: Counter,
+    pub limiter: Limiter,
     pub timeout: Timeout,
 }
-#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
-pub struct Counter {
-    pub request: HashMap<IpAddr, usize>,
-    pub response: HashMap<IpAddr, usize>,
-    pub gossipsub_message_block: HashMap<IpAddr, usize>,
-    pub gossipsub_message_transaction: HashMap<IpAddr, usize>,
-    pub gossipsub_message_stake: HashMap<IpAddr, usize>,
-    pub gossipsub_message_peers: HashMap<IpAddr, usize>,
+impl Ratelimit {
+    pub fn new(clock: SharedClock) -> Ratelimit {
+        Ratelimit {
+            limiter: Limiter {
+                clock: clock.clone(),
+                ..Default::default()
+            },
+            timeout: Timeout {
+                clock,
+                ..Default::default()
+            },
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rates {
+    pub request: usize,
+    pub response: usize,
+    pub gossipsub_message_block: usize,
+    pub gossipsub_message_compact_block: usize,
+    pub gossipsub_message_transaction: usize,
+    pub gossipsub_message_stake: usize,
+}
+impl Rates {
+    pub fn get(&self, endpoint: &Endpoint) -> usize {
+        match endpoint {
+            Endpoint::Request => self.request,
+            Endpoint::Response => self.response,
+            Endpoint::GossipsubMessageBlock => self.gossipsub_message_block,
+            Endpoint::GossipsubMessageCompactBlock => self.gossipsub_message_compact_block,
+            Endpoint::GossipsubMessageTransaction => self.gossipsub_message_transaction,
+            Endpoint::GossipsubMessageStake => self.gossipsub_message_stake,
+        }
+    }
+}
+impl Default for Rates {
+    fn default() -> Rates {
+        Rates {
+            request: P2P_RATELIMIT_REQUEST,
+            response: P2P_RATELIMIT_RESPONSE,
+            gossipsub_message_block: P2P_RATELIMIT_GOSSIPSUB_MESSAGE_BLOCK,
+            gossipsub_message_compact_block: P2P_RATELIMIT_GOSSIPSUB_MESSAGE_COMPACT_BLOCK,
+            gossipsub_message_transaction: P2P_RATELIMIT_GOSSIPSUB_MESSAGE_TRANSACTION,
+            gossipsub_message_stake: P2P_RATELIMIT_GOSSIPSUB_MESSAGE_STAKE,
+        }
+    }
 }
-impl Counter {
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bucket {
+    pub credit: u64,
+    pub timestamp: u64,
+}
+impl Bucket {
+    fn capacity(rate: usize) -> u64 {
+        rate as u64 * P2P_RATELIMIT_COST
+    }
+    fn refill(&mut self, rate: usize, millis: u64) {
+        let elapsed = millis.saturating_sub(self.timestamp);
+        self.credit = (self.credit + elapsed * rate as u64).min(Bucket::capacity(rate));
+        self.timestamp = millis;
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Limiter {
+    pub rates: Rates,
+    pub max_len: usize,
+    buckets: HashMap<(IpAddr, Endpoint), Bucket>,
+    #[serde(skip)]
+    pub clock: SharedClock,
+}
+impl Limiter {
+    pub fn new(rates: Rates, max_len: usize) -> Limiter {
+        Limiter {
+            rates,
+            max_len,
+            buckets: HashMap::new(),
+            clock: SharedClock::default(),
+        }
+    }
     pub fn add(&mut self, ip_addr: IpAddr, endpoint: &Endpoint) -> bool {
-        let map = match endpoint {
-            Endpoint::Request => &mut self.request,
-            Endpoint::Response => &mut self.response,
-            Endpoint::GossipsubMessageBlock => &mut self.gossipsub_message_block,
-            Endpoint::GossipsubMessageTransaction => &mut self.gossipsub_message_transaction,
-            Endpoint::GossipsubMessageStake => &mut self.gossipsub_message_stake,
-            Endpoint::GossipsubMessagePeers => &mut self.gossipsub_message_peers,
-        };
-        let limit = match endpoint {
-            Endpoint::Request => P2P_RATELIMIT_REQUEST,
-            Endpoint::Response => P2P_RATELIMIT_RESPONSE,
-            Endpoint::GossipsubMessageBlock => P2P_RATELIMIT_GOSSIPSUB_MESSAGE_BLOCK,
-            Endpoint::GossipsubMessageTransaction => P2P_RATELIMIT_GOSSIPSUB_MESSAGE_TRANSACTION,
-            Endpoint::GossipsubMessageStake => P2P_RATELIMIT_GOSSIPSUB_MESSAGE_STAKE,
-            Endpoint::GossipsubMessagePeers => P2P_RATELIMIT_GOSSIPSUB_MESSAGE_PEERS,
-        };
-        let mut i = *map.get(&ip_addr).unwrap_or(&0);
-        i += 1;
-        map.insert(ip_addr, i);
-        i > limit
-    }
-    pub fn clear(&mut self) {
-        self.request.clear();
-        self.response.clear();
-        self.gossipsub_message_block.clear();
-        self.gossipsub_message_transaction.clear();
-        self.gossipsub_message_stake.clear();
-        self.gossipsub_message_peers.clear();
+        let millis = self.clock.timestamp_millis() as u64;
+        self.add_at(ip_addr, endpoint, millis)
+    }
+    pub fn add_at(&mut self, ip_addr: IpAddr, endpoint: &Endpoint, millis: u64) -> bool {
+        let rate = self.rates.get(endpoint);
+        if !self.buckets.contains_key(&(ip_addr, *endpoint)) && self.buckets.len() >= self.max_len {
+            self.prune(millis);
+        }
+        let bucket = self.buckets.entry((ip_addr, *endpoint)).or_insert(Bucket {
+            credit: Bucket::capacity(rate),
+            timestamp: millis,
+        });
+        bucket.refill(rate, millis);
+        if bucket.credit < P2P_RATELIMIT_COST {
+            return true;
+        }
+        bucket.credit -= P2P_RATELIMIT_COST;
+        false
+    }
+    pub fn prune(&mut self, millis: u64) {
+        let rates = &self.rates;
+        self.buckets.retain(|(_, endpoint), bucket| {
+            let rate = rates.get(endpoint);
+            bucket.refill(rate, millis);
+            bucket.credit < Bucket::capacity(rate)
+        });
+        while self.buckets.len() >= self.max_len {
+            let key = match self.buckets.iter().min_by_key(|(_, a)| a.timestamp) {
+                Some((key, _)) => *key,
+                None => break,
+            };
+            self.buckets.remove(&key);
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+}
+impl Default for Limiter {
+    fn default() -> Limiter {
+        Limiter::new(Rates::default(), P2P_RATELIMIT_MAX_LEN)
     }
 }
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Timeout {
     pub request: HashMap<IpAddr, u32>,
     pub response: HashMap<IpAddr, u32>,
+    #[serde(skip)]
+    pub clock: SharedClock,
 }
 impl Timeout {
     pub fn insert(&mut self, ip_addr: IpAddr, endpoint: Endpoint) {
-        let map = match endpoint {
-            Endpoint::Request => &mut self.request,
-            Endpoint::Response => &mut self.response,
-            _ => unimplemented!(),
+        let timestamp = self.clock.timestamp();
+        let (map, limit) = match endpoint {
+            Endpoint::Request => (&mut self.request, P2P_RATELIMIT_REQUEST_TIMEOUT),
+            Endpoint::Response => (&mut self.response, P2P_RATELIMIT_RESPONSE_TIMEOUT),
+            Endpoint::GossipsubMessageBlock
+            | Endpoint::GossipsubMessageCompactBlock
+            | Endpoint::GossipsubMessageTransaction
+            | Endpoint::GossipsubMessageStake => return,
         };
-        map.insert(ip_addr, Utc::now().timestamp() as u32);
+        map.retain(|_, a| timestamp.saturating_sub(*a) < limit);
+        map.insert(ip_addr, timestamp);
     }
     pub fn has(&self, ip_addr: IpAddr, endpoint: Endpoint) -> bool {
-        let map = match endpoint {
-            Endpoint::Request => &self.request,
-            Endpoint::Response => &self.response,
-            _ => unimplemented!(),
+        let (map, limit) = match endpoint {
+            Endpoint::Request => (&self.request, P2P_RATELIMIT_REQUEST_TIMEOUT),
+            Endpoint::Response => (&self.response, P2P_RATELIMIT_RESPONSE_TIMEOUT),
+            Endpoint::GossipsubMessageBlock
+            | Endpoint::GossipsubMessageCompactBlock
+            | Endpoint::GossipsubMessageTransaction
+            | Endpoint::GossipsubMessageStake => return false,
         };
-        let limit = match endpoint {
-            Endpoint::Request => P2P_RATELIMIT_REQUEST_TIMEOUT,
-            Endpoint::Response => P2P_RATELIMIT_RESPONSE_TIMEOUT,
-            _ => unimplemented!(),
-        };
-        let timestamp = map.get(&ip_addr).unwrap_or(&0);
-        Utc::now().timestamp() as u32 - timestamp < limit
+        match map.get(&ip_addr) {
+            Some(timestamp) => self.clock.timestamp().saturating_sub(*timestamp) < limit,
+            None => false,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::ManualClock;
+    #[test]
+    fn test_limiter() {
+        let ip_addr = "127.0.0.1".parse().unwrap();
+        let mut limiter = Limiter::new(
+            Rates {
+                request: 2,
+                ..Default::default()
+            },
+            10,
+        );
+        assert!(!limiter.add_at(ip_addr, &Endpoint::Request, 0));
+        assert!(!limiter.add_at(ip_addr, &Endpoint::Request, 0));
+        assert!(limiter.add_at(ip_addr, &Endpoint::Request, 0));
+        assert!(limiter.add_at(ip_addr, &Endpoint::Request, 29_999));
+        assert!(!limiter.add_at(ip_addr, &Endpoint::Request, 30_000));
+        assert!(limiter.add_at(ip_addr, &Endpoint::Request, 30_000));
+        assert!(!limiter.add_at(ip_addr, &Endpoint::Response, 30_000));
+    }
+    #[test]
+    fn test_limiter_boundary_burst() {
+        let ip_addr = "127.0.0.1".parse().unwrap();
+        let mut limiter = Limiter::new(
+            Rates {
+                request: 60,
+                ..Default::default()
+            },
+            10,
+        );
+        let mut allowed = 0;
+        for millis in 59_000..61_000 {
+            if !limiter.add_at(ip_addr, &Endpoint::Request, millis) {
+                allowed += 1;
+            }
+        }
+        assert_eq!(allowed, 61);
+    }
+    #[test]
+    fn test_clock() {
+        let ip_addr = "127.0.0.1".parse().unwrap();
+        let manual = ManualClock::new(0);
+        let mut ratelimit = Ratelimit::new(SharedClock::new(manual.clone()));
+        ratelimit.limiter.rates.request = 1;
+        assert!(!ratelimit.limiter.add(ip_addr, &Endpoint::Request));
+        assert!(ratelimit.limiter.add(ip_addr, &Endpoint::Request));
+        manual.advance(60_000);
+        assert!(!ratelimit.limiter.add(ip_addr, &Endpoint::Request));
+        ratelimit.timeout.insert(ip_addr, Endpoint::Request);
+        assert!(ratelimit.timeout.has(ip_addr, Endpoint::Request));
+        manual.advance(P2P_RATELIMIT_REQUEST_TIMEOUT as i64 * 1000);
+        assert!(!ratelimit.timeout.has(ip_addr, Endpoint::Request));
+        ratelimit
+            .timeout
+            .insert(ip_addr, Endpoint::GossipsubMessageBlock);
+        assert!(!ratelimit
+            .timeout
+            .has(ip_addr, Endpoint::GossipsubMessageBlock));
+    }
+    #[test]
+    fn test_limiter_max_len() {
+        let mut limiter = Limiter::new(Rates::default(), 2);
+        for i in 0..10 {
+            let ip_addr = IpAddr::from([10, 0, 0, i]);
+            limiter.add_at(ip_addr, &Endpoint::Request, i as u64);
+        }
+        assert!(limiter.len() <= 2);
+        limiter.prune(3_600_000);
+        assert!(limiter.is_empty());
     }
 }
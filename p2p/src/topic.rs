@@ -0,0 +1,78 @@
+use super::NETWORK_MAINNET;
+use super::NETWORK_TESTNET;
+use libp2p::gossipsub::IdentTopic;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topic {
+    Block,
+    CompactBlock,
+    Transaction,
+    Stake,
+}
+impl Topic {
+    pub const ALL: [Topic; 4] = [
+        Topic::Block,
+        Topic::CompactBlock,
+        Topic::Transaction,
+        Topic::Stake,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topic::Block => "block",
+            Topic::CompactBlock => "compact_block",
+            Topic::Transaction => "transaction",
+            Topic::Stake => "stake",
+        }
+    }
+    pub fn version(&self) -> u32 {
+        match self {
+            Topic::Block => 1,
+            Topic::CompactBlock => 1,
+            Topic::Transaction => 1,
+            Topic::Stake => 1,
+        }
+    }
+    pub fn ident_topic(&self, network: u8) -> IdentTopic {
+        IdentTopic::new(format!(
+            "/tofuri/{}/{}/{}",
+            network_name(network),
+            self.name(),
+            self.version()
+        ))
+    }
+    pub fn legacy_ident_topic(&self) -> IdentTopic {
+        IdentTopic::new(self.name())
+    }
+    pub fn ident_topics(&self, network: u8) -> [IdentTopic; 2] {
+        [self.ident_topic(network), self.legacy_ident_topic()]
+    }
+}
+pub fn network_name(network: u8) -> String {
+    match network {
+        NETWORK_MAINNET => "mainnet".to_string(),
+        NETWORK_TESTNET => "testnet".to_string(),
+        network => network.to_string(),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_ident_topic() {
+        assert_eq!(
+            Topic::Block.ident_topic(NETWORK_MAINNET).to_string(),
+            "/tofuri/mainnet/block/1"
+        );
+        assert_eq!(
+            Topic::Stake.ident_topic(NETWORK_TESTNET).to_string(),
+            "/tofuri/testnet/stake/1"
+        );
+        assert_ne!(
+            Topic::Block.ident_topic(NETWORK_MAINNET).hash(),
+            Topic::Block.ident_topic(NETWORK_TESTNET).hash()
+        );
+        assert_eq!(
+            Topic::CompactBlock.legacy_ident_topic().to_string(),
+            "compact_block"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+pub mod scheduler;
 use lazy_static::lazy_static;
 use serde::Deserialize;
 use serde::Serialize;
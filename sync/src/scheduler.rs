@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::Hash;
+#[derive(Debug, Clone, PartialEq)]
+pub struct InFlight {
+    pub hashes: Vec<[u8; 32]>,
+    pub timestamp: u32,
+}
+#[derive(Debug, Clone)]
+pub struct Scheduler<P> {
+    pub timeout: u32,
+    in_flight: HashMap<P, InFlight>,
+    failures: HashMap<[u8; 32], usize>,
+    penalties: HashMap<P, usize>,
+}
+impl<P: Clone + Eq + Hash> Scheduler<P> {
+    pub fn new(timeout: u32) -> Scheduler<P> {
+        Scheduler {
+            timeout,
+            in_flight: HashMap::new(),
+            failures: HashMap::new(),
+            penalties: HashMap::new(),
+        }
+    }
+    pub fn schedule(
+        &mut self,
+        missing: &[[u8; 32]],
+        peers: &[P],
+        chunk: usize,
+        timestamp: u32,
+    ) -> Vec<(P, Vec<[u8; 32]>)> {
+        let timeout = self.timeout;
+        let expired: Vec<P> = self
+            .in_flight
+            .iter()
+            .filter(|(_, a)| timestamp.saturating_sub(a.timestamp) >= timeout)
+            .map(|(peer, _)| peer.clone())
+            .collect();
+        for peer in expired {
+            self.fail(&peer);
+        }
+        let assigned: HashSet<&[u8; 32]> = self
+            .in_flight
+            .values()
+            .flat_map(|a| a.hashes.iter())
+            .collect();
+        let unassigned: Vec<[u8; 32]> = missing
+            .iter()
+            .filter(|a| !assigned.contains(a))
+            .copied()
+            .collect();
+        let mut chunks = unassigned.chunks(chunk.max(1));
+        let mut peers = peers.to_vec();
+        peers.sort_by_key(|a| self.penalties.get(a).copied().unwrap_or_default());
+        let mut vec = vec![];
+        for peer in peers.iter() {
+            if self.in_flight.contains_key(peer) {
+                continue;
+            }
+            let hashes = match chunks.next() {
+                Some(x) => x.to_vec(),
+                None => break,
+            };
+            vec.push((peer.clone(), hashes));
+        }
+        for (peer, hashes) in vec.iter() {
+            self.in_flight.insert(
+                peer.clone(),
+                InFlight {
+                    hashes: hashes.clone(),
+                    timestamp,
+                },
+            );
+        }
+        vec
+    }
+    pub fn complete(&mut self, peer: &P) -> Option<InFlight> {
+        self.penalties.remove(peer);
+        let in_flight = self.in_flight.remove(peer)?;
+        for hash in in_flight.hashes.iter() {
+            self.failures.remove(hash);
+        }
+        Some(in_flight)
+    }
+    pub fn fail(&mut self, peer: &P) -> Option<InFlight> {
+        *self.penalties.entry(peer.clone()).or_default() += 1;
+        let in_flight = self.in_flight.remove(peer)?;
+        for hash in in_flight.hashes.iter() {
+            *self.failures.entry(*hash).or_default() += 1;
+        }
+        Some(in_flight)
+    }
+    pub fn remove(&mut self, peer: &P) -> Option<InFlight> {
+        let in_flight = self.fail(peer);
+        self.penalties.remove(peer);
+        in_flight
+    }
+    pub fn failures(&self) -> usize {
+        self.failures.values().max().copied().unwrap_or_default()
+    }
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+    pub fn clear(&mut self) {
+        self.in_flight.clear();
+        self.failures.clear();
+        self.penalties.clear();
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_schedule() {
+        let mut scheduler = Scheduler::new(10);
+        let missing: Vec<[u8; 32]> = (0..5).map(|i| [i; 32]).collect();
+        let vec = scheduler.schedule(&missing, &[1, 2, 3, 4], 2, 0);
+        assert_eq!(
+            vec,
+            vec![
+                (1, vec![[0; 32], [1; 32]]),
+                (2, vec![[2; 32], [3; 32]]),
+                (3, vec![[4; 32]]),
+            ]
+        );
+        assert_eq!(scheduler.in_flight(), 3);
+        assert!(scheduler.schedule(&missing, &[1, 2, 3, 4], 2, 1).is_empty());
+        scheduler.fail(&2);
+        assert_eq!(
+            scheduler.schedule(&missing, &[1, 2, 3, 4], 2, 2),
+            vec![(4, vec![[2; 32], [3; 32]])]
+        );
+    }
+    #[test]
+    fn test_timeout() {
+        let mut scheduler = Scheduler::new(10);
+        let missing = [[0; 32]];
+        assert_eq!(scheduler.schedule(&missing, &[1, 2], 1, 0).len(), 1);
+        assert!(scheduler.schedule(&missing, &[1, 2], 1, 9).is_empty());
+        assert_eq!(
+            scheduler.schedule(&missing, &[2, 1], 1, 10),
+            vec![(2, vec![[0; 32]])]
+        );
+    }
+    #[test]
+    fn test_failures() {
+        let mut scheduler = Scheduler::new(10);
+        let missing = [[0; 32], [1; 32]];
+        scheduler.schedule(&missing, &[1, 2], 1, 0);
+        scheduler.fail(&1);
+        assert_eq!(scheduler.failures(), 1);
+        scheduler.schedule(&missing, &[1], 1, 1);
+        scheduler.fail(&1);
+        assert_eq!(scheduler.failures(), 2);
+        assert!(scheduler.schedule(&missing, &[1], 1, 10).is_empty());
+        assert_eq!(scheduler.failures(), 2);
+        scheduler.schedule(&missing, &[1], 1, 11);
+        assert_eq!(scheduler.failures(), 3);
+        scheduler.complete(&1);
+        assert_eq!(scheduler.failures(), 1);
+        scheduler.clear();
+        assert_eq!(scheduler.failures(), 0);
+    }
+    #[test]
+    fn test_penalties() {
+        let mut scheduler = Scheduler::new(10);
+        let missing = [[0; 32], [1; 32]];
+        scheduler.schedule(&missing, &[1, 2], 1, 0);
+        scheduler.fail(&1);
+        scheduler.complete(&2);
+        assert_eq!(
+            scheduler.schedule(&missing, &[1, 2], 1, 1),
+            vec![(2, vec![[0; 32]]), (1, vec![[1; 32]])]
+        );
+        scheduler.remove(&1);
+        scheduler.complete(&2);
+        assert_eq!(
+            scheduler.schedule(&missing, &[1, 2], 1, 2),
+            vec![(1, vec![[0; 32]]), (2, vec![[1; 32]])]
+        );
+    }
+}
@@ -3,13 +3,30 @@ use crate::CARGO_PKG_NAME;
 use crate::CARGO_PKG_REPOSITORY;
 use crate::CARGO_PKG_VERSION;
 use crate::GIT_HASH;
+use crate::HISTORY_PAGE_LEN;
 use address::public;
+use api::AccessHex;
+use api::AddressBookHex;
+use api::AtHeight;
 use api::BlockHex;
+use api::HistoryHex;
+use api::LatencyHex;
+use api::MetricsHex;
+use api::NatHex;
+use api::NetworkTimeHex;
+use api::Page;
+use api::PeerStatusHex;
+use api::ProofHex;
 use api::Root;
+use api::ScoreHex;
 use api::StakeHex;
+use api::TrafficHex;
 use api::TransactionHex;
+use api::TransactionStatusHex;
 use axum::extract::Path;
+use axum::extract::Query;
 use axum::extract::State;
+use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::routing::get;
 use axum::routing::post;
@@ -17,9 +34,22 @@ use axum::Json;
 use axum::Router;
 use axum::Server;
 use block::Block;
-use chrono::offset::Utc;
+use block::Header;
+use block::Proof;
+use blockchain::TransactionStatus;
+use clock::Clock;
+use db::history::HistoryDB;
+use db::peer::PeerDB;
+use db::peer::Source;
+use db::score::ScoreDB;
 use fork::BLOCK_TIME;
 use hex;
+use libp2p::autonat::NatStatus;
+use multiaddr::ToIpAddr;
+use p2p::behaviour::Status;
+use p2p::metrics::Counters;
+use p2p::metrics::Latency;
+use p2p::topic::Topic;
 use serde::de::DeserializeOwned;
 use stake::Stake;
 use std::convert::TryInto;
@@ -34,24 +64,41 @@ use tracing::error;
 use transaction::Transaction;
 pub enum Call {
     Balance([u8; 20]),
+    BalanceAt([u8; 20], usize),
     BalancePendingMin([u8; 20]),
     BalancePendingMax([u8; 20]),
     Staked([u8; 20]),
+    StakedAt([u8; 20], usize),
     StakedPendingMin([u8; 20]),
     StakedPendingMax([u8; 20]),
+    History([u8; 20], usize),
     Height,
     HeightByHash([u8; 32]),
     BlockLatest,
     HashByHeight(usize),
     BlockByHash([u8; 32]),
     TransactionByHash([u8; 32]),
+    TransactionStatus([u8; 32]),
+    TransactionProof([u8; 32]),
     StakeByHash([u8; 32]),
+    PeerId,
     Peers,
-    Peer(IpAddr),
+    PeerStatuses,
+    Peer(String),
+    AddressBook,
+    Scores,
+    Ban(IpAddr),
+    Unban(IpAddr),
+    Access,
+    AccessReload,
+    Metrics,
+    Nat,
+    NetworkTime,
     Transaction(Transaction),
     Stake(Stake),
     Address,
     Ticks,
+    Time,
     TreeSize,
     Sync,
     RandomQueue,
@@ -71,10 +118,13 @@ pub struct APIClient {
 }
 impl APIClient {
     pub async fn call<T: DeserializeOwned>(&self, call: Call) -> T {
+        self.try_call(call).await.unwrap()
+    }
+    pub async fn try_call<T: DeserializeOwned>(&self, call: Call) -> Option<T> {
         let (tx, rx) = oneshot::channel();
         let _ = self.tx.send(Request { call, tx }).await;
-        let response = rx.await.unwrap();
-        bincode::deserialize(&response.0).unwrap()
+        let response = rx.await.ok()?;
+        bincode::deserialize(&response.0).ok()
     }
 }
 pub struct Request {
@@ -96,15 +146,24 @@ pub fn spawn(api_client: APIClient, addr: &SocketAddr) {
         .route("/staked/:address", get(e::staked))
         .route("/staked_pending_min/:address", get(e::staked_pending_min))
         .route("/staked_pending_max/:address", get(e::staked_pending_max))
+        .route("/address/:address/history", get(e::history))
         .route("/height", get(e::height))
         .route("/height/:hash", get(e::height_by_hash))
         .route("/block", get(e::block_latest))
         .route("/hash/:height", get(e::hash_by_height))
         .route("/block/:hash", get(e::block_by_hash))
         .route("/transaction/:hash", get(e::transaction_by_hash))
+        .route("/transaction/:hash/status", get(e::transaction_status))
+        .route("/proof/transaction/:hash", get(e::transaction_proof))
         .route("/stake/:hash", get(e::stake_by_hash))
+        .route("/peer_id", get(e::peer_id))
         .route("/peers", get(e::peers))
-        .route("/peer/:ip_addr", get(e::peer))
+        .route("/peers/status", get(e::peer_statuses))
+        .route("/peer/:address", get(e::peer))
+        .route("/peers/book", get(e::address_book))
+        .route("/peers/scores", get(e::scores))
+        .route("/metrics", get(e::metrics))
+        .route("/nat", get(e::nat))
         .route("/transaction", post(e::transaction))
         .route("/stake", post(e::stake))
         .route("/cargo_pkg_name", get(e::cargo_pkg_name))
@@ -114,6 +173,7 @@ pub fn spawn(api_client: APIClient, addr: &SocketAddr) {
         .route("/address", get(e::address))
         .route("/ticks", get(e::ticks))
         .route("/time", get(e::time))
+        .route("/network_time", get(e::network_time))
         .route("/tree_size", get(e::tree_size))
         .route("/sync", get(e::sync))
         .route("/random_queue", get(e::random_queue))
@@ -130,27 +190,52 @@ pub fn spawn(api_client: APIClient, addr: &SocketAddr) {
     let make_service = router.into_make_service();
     tokio::spawn(async { builder.serve(make_service).await });
 }
+pub fn control(api_client: APIClient) -> Router {
+    Router::new()
+        .route("/peer/:ip_addr/ban", post(e::ban))
+        .route("/peer/:ip_addr/unban", post(e::unban))
+        .route("/access", get(e::access))
+        .route("/access/reload", post(e::access_reload))
+        .with_state(api_client)
+}
 pub async fn accept(node: &mut Node, request: Request) {
     let res = match request.call {
         Call::Balance(a) => i::balance(node, a),
+        Call::BalanceAt(a, b) => i::balance_at(node, a, b),
         Call::BalancePendingMin(a) => i::balance_pending_min(node, a),
         Call::BalancePendingMax(a) => i::balance_pending_max(node, a),
         Call::Staked(a) => i::staked(node, a),
+        Call::StakedAt(a, b) => i::staked_at(node, a, b),
         Call::StakedPendingMin(a) => i::staked_pending_min(node, a),
         Call::StakedPendingMax(a) => i::staked_pending_max(node, a),
+        Call::History(a, b) => i::history(node, a, b),
         Call::Height => i::height(node),
         Call::HeightByHash(a) => i::height_by_hash(node, a),
         Call::BlockLatest => i::block_latest(node),
         Call::HashByHeight(a) => i::hash_by_height(node, a),
         Call::BlockByHash(a) => i::block_by_hash(node, a),
         Call::TransactionByHash(a) => i::transaction_by_hash(node, a),
+        Call::TransactionStatus(a) => i::transaction_status(node, a),
+        Call::TransactionProof(a) => i::transaction_proof(node, a),
         Call::StakeByHash(a) => i::stake_by_hash(node, a),
+        Call::PeerId => i::peer_id(node),
         Call::Peers => i::peers(node),
+        Call::PeerStatuses => i::peer_statuses(node),
         Call::Peer(a) => i::peer(node, a),
+        Call::AddressBook => i::address_book(node),
+        Call::Scores => i::scores(node),
+        Call::Ban(a) => i::ban(node, a),
+        Call::Unban(a) => i::unban(node, a),
+        Call::Access => i::access(node),
+        Call::AccessReload => i::access_reload(node),
+        Call::Metrics => i::metrics(node),
+        Call::Nat => i::nat(node),
+        Call::NetworkTime => i::network_time(node),
         Call::Transaction(a) => i::transaction(node, a),
         Call::Stake(a) => i::stake(node, a),
         Call::Address => i::address(node),
         Call::Ticks => i::ticks(node),
+        Call::Time => i::time(node),
         Call::TreeSize => i::tree_size(node),
         Call::Sync => i::sync(node),
         Call::RandomQueue => i::random_queue(node),
@@ -172,12 +257,13 @@ pub async fn accept(node: &mut Node, request: Request) {
 }
 pub mod e {
     use super::*;
-    pub async fn root() -> impl IntoResponse {
+    pub async fn root(State(c): State<APIClient>) -> impl IntoResponse {
         Json(Root {
             cargo_pkg_name: CARGO_PKG_NAME.to_string(),
             cargo_pkg_version: CARGO_PKG_VERSION.to_string(),
             cargo_pkg_repository: CARGO_PKG_REPOSITORY.to_string(),
             git_hash: GIT_HASH.to_string(),
+            peer_id: c.call::<String>(Call::PeerId).await,
         })
     }
     pub async fn cargo_pkg_name() -> impl IntoResponse {
@@ -192,9 +278,20 @@ pub mod e {
     pub async fn git_hash() -> impl IntoResponse {
         Json(GIT_HASH)
     }
-    pub async fn balance(State(c): State<APIClient>, address: Path<String>) -> impl IntoResponse {
+    pub async fn balance(
+        State(c): State<APIClient>,
+        address: Path<String>,
+        Query(at_height): Query<AtHeight>,
+    ) -> Result<Json<u128>, StatusCode> {
         let address_bytes = public::decode(&address).unwrap();
-        Json(c.call::<u128>(Call::Balance(address_bytes)).await)
+        let call = match at_height.height {
+            Some(height) => Call::BalanceAt(address_bytes, height),
+            None => Call::Balance(address_bytes),
+        };
+        c.try_call::<u128>(call)
+            .await
+            .map(Json)
+            .ok_or(StatusCode::NOT_FOUND)
     }
     pub async fn balance_pending_min(
         State(c): State<APIClient>,
@@ -210,9 +307,20 @@ pub mod e {
         let address_bytes = public::decode(&address).unwrap();
         Json(c.call::<u128>(Call::BalancePendingMax(address_bytes)).await)
     }
-    pub async fn staked(State(c): State<APIClient>, address: Path<String>) -> impl IntoResponse {
+    pub async fn staked(
+        State(c): State<APIClient>,
+        address: Path<String>,
+        Query(at_height): Query<AtHeight>,
+    ) -> Result<Json<u128>, StatusCode> {
         let address_bytes = public::decode(&address).unwrap();
-        Json(c.call::<u128>(Call::Staked(address_bytes)).await)
+        let call = match at_height.height {
+            Some(height) => Call::StakedAt(address_bytes, height),
+            None => Call::Staked(address_bytes),
+        };
+        c.try_call::<u128>(call)
+            .await
+            .map(Json)
+            .ok_or(StatusCode::NOT_FOUND)
     }
     pub async fn staked_pending_min(
         State(c): State<APIClient>,
@@ -228,6 +336,25 @@ pub mod e {
         let address_bytes = public::decode(&address).unwrap();
         Json(c.call::<u128>(Call::StakedPendingMax(address_bytes)).await)
     }
+    pub async fn history(
+        State(c): State<APIClient>,
+        address: Path<String>,
+        Query(page): Query<Page>,
+    ) -> impl IntoResponse {
+        let address_bytes = public::decode(&address).unwrap();
+        Json(
+            c.call::<Vec<HistoryDB>>(Call::History(address_bytes, page.page))
+                .await
+                .into_iter()
+                .map(|x| HistoryHex {
+                    height: x.height,
+                    hash: hex::encode(x.hash),
+                    block_hash: hex::encode(x.block_hash),
+                    direction: x.direction.to_string(),
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
     pub async fn height(State(c): State<APIClient>) -> impl IntoResponse {
         Json(c.call::<usize>(Call::Height).await)
     }
@@ -270,6 +397,37 @@ pub mod e {
         let transaction_hex: TransactionHex = transaction.try_into().unwrap();
         Json(transaction_hex)
     }
+    pub async fn transaction_status(
+        State(c): State<APIClient>,
+        hash: Path<String>,
+    ) -> impl IntoResponse {
+        let hash: [u8; 32] = hex::decode(hash.clone()).unwrap().try_into().unwrap();
+        let transaction_status = c
+            .call::<TransactionStatus>(Call::TransactionStatus(hash))
+            .await;
+        Json(TransactionStatusHex {
+            status: transaction_status.status.to_string(),
+            confirmations: transaction_status.confirmations,
+            block_hash: transaction_status.block_hash.map(hex::encode),
+            height: transaction_status.height,
+        })
+    }
+    pub async fn transaction_proof(
+        State(c): State<APIClient>,
+        hash: Path<String>,
+    ) -> Result<Json<ProofHex>, StatusCode> {
+        let hash: [u8; 32] = hex::decode(hash.clone()).unwrap().try_into().unwrap();
+        let (header, height, proof) = c
+            .try_call::<(Header, usize, Proof)>(Call::TransactionProof(hash))
+            .await
+            .ok_or(StatusCode::NOT_FOUND)?;
+        Ok(Json(ProofHex {
+            header: header.into(),
+            height,
+            indices: proof.indices,
+            lemmas: proof.lemmas.iter().map(hex::encode).collect(),
+        }))
+    }
     pub async fn stake_by_hash(
         State(c): State<APIClient>,
         hash: Path<String>,
@@ -279,15 +437,129 @@ pub mod e {
         let stake_hex: StakeHex = stake.try_into().unwrap();
         Json(stake_hex)
     }
+    pub async fn peer_id(State(c): State<APIClient>) -> impl IntoResponse {
+        Json(c.call::<String>(Call::PeerId).await)
+    }
     pub async fn peers(State(c): State<APIClient>) -> impl IntoResponse {
         Json(c.call::<Vec<IpAddr>>(Call::Peers).await)
     }
+    pub async fn peer_statuses(State(c): State<APIClient>) -> impl IntoResponse {
+        Json(
+            c.call::<Vec<(String, Status)>>(Call::PeerStatuses)
+                .await
+                .into_iter()
+                .map(|(peer_id, x)| PeerStatusHex {
+                    peer_id,
+                    version: x.version,
+                    network: x.network,
+                    genesis: hex::encode(x.genesis),
+                    height: x.height,
+                    hash: hex::encode(x.hash),
+                    compact_blocks: x.compact_blocks,
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
     pub async fn peer(
+        State(c): State<APIClient>,
+        Path(address): Path<String>,
+    ) -> impl IntoResponse {
+        Json(c.call::<bool>(Call::Peer(address)).await)
+    }
+    pub async fn address_book(State(c): State<APIClient>) -> impl IntoResponse {
+        Json(
+            c.call::<Vec<(String, PeerDB, bool)>>(Call::AddressBook)
+                .await
+                .into_iter()
+                .map(|(address, x, banned)| AddressBookHex {
+                    address,
+                    source: x.source.to_string(),
+                    last_success: x.last_success,
+                    last_failure: x.last_failure,
+                    failures: x.failures,
+                    banned,
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+    pub async fn scores(State(c): State<APIClient>) -> impl IntoResponse {
+        Json(
+            c.call::<Vec<(IpAddr, ScoreDB)>>(Call::Scores)
+                .await
+                .into_iter()
+                .map(|(ip_addr, x)| ScoreHex {
+                    ip_addr: ip_addr.to_string(),
+                    score: x.score(),
+                    invalid: x.invalid,
+                    ratelimited: x.ratelimited,
+                    useful: x.useful,
+                    uptime: x.uptime,
+                    banned_until: x.banned_until,
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+    pub async fn access(State(c): State<APIClient>) -> impl IntoResponse {
+        let (allow, deny) = c.call::<(Vec<String>, Vec<String>)>(Call::Access).await;
+        Json(AccessHex { allow, deny })
+    }
+    pub async fn access_reload(State(c): State<APIClient>) -> impl IntoResponse {
+        Json(c.call::<String>(Call::AccessReload).await)
+    }
+    pub async fn metrics(State(c): State<APIClient>) -> impl IntoResponse {
+        fn traffic(name: String, x: Counters) -> TrafficHex {
+            TrafficHex {
+                name,
+                messages_in: x.messages_in,
+                messages_out: x.messages_out,
+                bytes_in: x.bytes_in,
+                bytes_out: x.bytes_out,
+                rejected: x.rejected,
+                ratelimited: x.ratelimited,
+            }
+        }
+        let (peers, topics, sync, latency) = c
+            .call::<(
+                Vec<(String, Counters)>,
+                Vec<(String, Counters)>,
+                Counters,
+                Latency,
+            )>(Call::Metrics)
+            .await;
+        Json(MetricsHex {
+            peers: peers.into_iter().map(|(a, b)| traffic(a, b)).collect(),
+            topics: topics.into_iter().map(|(a, b)| traffic(a, b)).collect(),
+            sync: traffic("sync".to_string(), sync),
+            latency: LatencyHex {
+                count: latency.count,
+                mean: latency.mean(),
+                max: latency.max,
+                failures: latency.failures,
+            },
+        })
+    }
+    pub async fn nat(State(c): State<APIClient>) -> impl IntoResponse {
+        let (status, public_address, confidence, relay, reservations) = c
+            .call::<(String, Option<String>, usize, bool, Vec<String>)>(Call::Nat)
+            .await;
+        Json(NatHex {
+            status,
+            public_address,
+            confidence,
+            relay,
+            reservations,
+        })
+    }
+    pub async fn ban(State(c): State<APIClient>, Path(ip_addr): Path<String>) -> impl IntoResponse {
+        let ip_addr = ip_addr.parse().unwrap();
+        Json(c.call::<bool>(Call::Ban(ip_addr)).await)
+    }
+    pub async fn unban(
         State(c): State<APIClient>,
         Path(ip_addr): Path<String>,
     ) -> impl IntoResponse {
         let ip_addr = ip_addr.parse().unwrap();
-        Json(c.call::<bool>(Call::Peer(ip_addr)).await)
+        Json(c.call::<bool>(Call::Unban(ip_addr)).await)
     }
     pub async fn transaction(
         State(c): State<APIClient>,
@@ -309,8 +581,17 @@ pub mod e {
     pub async fn ticks(State(c): State<APIClient>) -> impl IntoResponse {
         Json(c.call::<usize>(Call::Ticks).await)
     }
-    pub async fn time() -> impl IntoResponse {
-        Json(chrono::offset::Utc::now().timestamp_millis())
+    pub async fn time(State(c): State<APIClient>) -> impl IntoResponse {
+        Json(c.call::<i64>(Call::Time).await)
+    }
+    pub async fn network_time(State(c): State<APIClient>) -> impl IntoResponse {
+        let (offset, median, samples) =
+            c.call::<(i64, Option<i64>, usize)>(Call::NetworkTime).await;
+        Json(NetworkTimeHex {
+            offset,
+            median,
+            samples,
+        })
     }
     pub async fn tree_size(State(c): State<APIClient>) -> impl IntoResponse {
         Json(c.call::<usize>(Call::TreeSize).await)
@@ -367,7 +648,8 @@ pub mod e {
             return Json(-1.0);
         }
         let block = c.call::<Block>(Call::BlockLatest).await;
-        let mut diff = (Utc::now().timestamp() as u32).saturating_sub(block.timestamp) as f32;
+        let timestamp = (c.call::<i64>(Call::Time).await / 1000) as u32;
+        let mut diff = timestamp.saturating_sub(block.timestamp) as f32;
         diff /= BLOCK_TIME as f32;
         diff /= sync.bps;
         Json(diff)
@@ -384,6 +666,15 @@ pub mod i {
     pub fn balance(node: &mut Node, address: [u8; 20]) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.blockchain.balance(&address)).map_err(Error::Bincode)
     }
+    pub fn balance_at(node: &mut Node, address: [u8; 20], height: usize) -> Result<Vec<u8>, Error> {
+        bincode::serialize(
+            &node
+                .blockchain
+                .balance_at(&node.db, &address, height)
+                .map_err(Error::Blockchain)?,
+        )
+        .map_err(Error::Bincode)
+    }
     pub fn balance_pending_min(node: &mut Node, address: [u8; 20]) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.blockchain.balance_pending_min(&address)).map_err(Error::Bincode)
     }
@@ -393,12 +684,35 @@ pub mod i {
     pub fn staked(node: &mut Node, address: [u8; 20]) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.blockchain.staked(&address)).map_err(Error::Bincode)
     }
+    pub fn staked_at(node: &mut Node, address: [u8; 20], height: usize) -> Result<Vec<u8>, Error> {
+        bincode::serialize(
+            &node
+                .blockchain
+                .staked_at(&node.db, &address, height)
+                .map_err(Error::Blockchain)?,
+        )
+        .map_err(Error::Bincode)
+    }
     pub fn staked_pending_min(node: &mut Node, address: [u8; 20]) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.blockchain.staked_pending_min(&address)).map_err(Error::Bincode)
     }
     pub fn staked_pending_max(node: &mut Node, address: [u8; 20]) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.blockchain.staked_pending_max(&address)).map_err(Error::Bincode)
     }
+    pub fn history(node: &mut Node, address: [u8; 20], page: usize) -> Result<Vec<u8>, Error> {
+        bincode::serialize(
+            &node
+                .blockchain
+                .history(
+                    &node.db,
+                    &address,
+                    page * HISTORY_PAGE_LEN,
+                    HISTORY_PAGE_LEN,
+                )
+                .map_err(Error::Blockchain)?,
+        )
+        .map_err(Error::Bincode)
+    }
     pub fn height(node: &mut Node) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.blockchain.height()).map_err(Error::Bincode)
     }
@@ -431,16 +745,152 @@ pub mod i {
         bincode::serialize(&db::transaction::get(&node.db, &hash).map_err(Error::DB)?)
             .map_err(Error::Bincode)
     }
+    pub fn transaction_status(node: &mut Node, hash: [u8; 32]) -> Result<Vec<u8>, Error> {
+        bincode::serialize(
+            &node
+                .blockchain
+                .transaction_status(&node.db, &hash)
+                .map_err(Error::Blockchain)?,
+        )
+        .map_err(Error::Bincode)
+    }
+    pub fn transaction_proof(node: &mut Node, hash: [u8; 32]) -> Result<Vec<u8>, Error> {
+        bincode::serialize(
+            &node
+                .blockchain
+                .transaction_proof(&node.db, &hash)
+                .map_err(Error::Blockchain)?,
+        )
+        .map_err(Error::Bincode)
+    }
     pub fn stake_by_hash(node: &mut Node, hash: [u8; 32]) -> Result<Vec<u8>, Error> {
         bincode::serialize(&db::stake::get(&node.db, &hash).map_err(Error::DB)?)
             .map_err(Error::Bincode)
     }
+    pub fn peer_id(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&node.p2p.swarm.local_peer_id().to_string()).map_err(Error::Bincode)
+    }
     pub fn peers(node: &mut Node) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.p2p.connections.values().collect::<Vec<_>>())
             .map_err(Error::Bincode)
     }
-    pub fn peer(node: &mut Node, ip_addr: IpAddr) -> Result<Vec<u8>, Error> {
-        bincode::serialize(&node.p2p.connections_unknown.insert(ip_addr)).map_err(Error::Bincode)
+    pub fn peer_statuses(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(
+            &node
+                .p2p
+                .statuses
+                .iter()
+                .map(|(peer_id, status)| (peer_id.to_string(), status.clone()))
+                .collect::<Vec<_>>(),
+        )
+        .map_err(Error::Bincode)
+    }
+    pub fn peer(node: &mut Node, address: String) -> Result<Vec<u8>, Error> {
+        let inserted = match multiaddr::parse(&address, node.args.testnet) {
+            Some(multiaddr) => node.book_insert(&multiaddr, Source::Api),
+            None => false,
+        };
+        bincode::serialize(&inserted).map_err(Error::Bincode)
+    }
+    pub fn address_book(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(
+            &db::peer::get_all(&node.db)
+                .map_err(Error::DB)?
+                .into_iter()
+                .map(|(address, peer)| {
+                    let banned = multiaddr::parse(&address, node.args.testnet)
+                        .and_then(|x| x.ip_addr())
+                        .map(|x| node.banned(x))
+                        .unwrap_or_default();
+                    (address, peer, banned)
+                })
+                .collect::<Vec<_>>(),
+        )
+        .map_err(Error::Bincode)
+    }
+    pub fn scores(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&node.scores.iter().collect::<Vec<_>>()).map_err(Error::Bincode)
+    }
+    pub fn access(node: &mut Node) -> Result<Vec<u8>, Error> {
+        let access = &node.p2p.access;
+        bincode::serialize(&(
+            access
+                .allow
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>(),
+            access
+                .deny
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>(),
+        ))
+        .map_err(Error::Bincode)
+    }
+    pub fn access_reload(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&match node.access_reload() {
+            Ok(()) => "success".to_string(),
+            Err(e) => {
+                error!(?e);
+                format!("{:?}", e)
+            }
+        })
+        .map_err(Error::Bincode)
+    }
+    pub fn metrics(node: &mut Node) -> Result<Vec<u8>, Error> {
+        let metrics = &node.p2p.metrics;
+        bincode::serialize(&(
+            metrics
+                .peers
+                .iter()
+                .map(|(a, b)| (a.to_string(), *b))
+                .collect::<Vec<_>>(),
+            metrics
+                .topics
+                .iter()
+                .map(|(a, b)| (a.name().to_string(), *b))
+                .collect::<Vec<_>>(),
+            metrics.sync,
+            metrics.latency,
+        ))
+        .map_err(Error::Bincode)
+    }
+    pub fn nat(node: &mut Node) -> Result<Vec<u8>, Error> {
+        let behaviour = node.p2p.swarm.behaviour();
+        let status = match behaviour.autonat.nat_status() {
+            NatStatus::Public(_) => "public",
+            NatStatus::Private => "private",
+            NatStatus::Unknown => "unknown",
+        };
+        bincode::serialize(&(
+            status.to_string(),
+            behaviour.autonat.public_address().map(|x| x.to_string()),
+            behaviour.autonat.confidence(),
+            behaviour.relay.is_enabled(),
+            node.p2p
+                .reservations
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>(),
+        ))
+        .map_err(Error::Bincode)
+    }
+    pub fn network_time(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&(
+            node.clock.offset(),
+            node.p2p.network_time.median(),
+            node.p2p.network_time.len(),
+        ))
+        .map_err(Error::Bincode)
+    }
+    pub fn ban(node: &mut Node, ip_addr: IpAddr) -> Result<Vec<u8>, Error> {
+        node.ban(ip_addr, u32::MAX);
+        node.disconnect(ip_addr);
+        bincode::serialize(&true).map_err(Error::Bincode)
+    }
+    pub fn unban(node: &mut Node, ip_addr: IpAddr) -> Result<Vec<u8>, Error> {
+        node.ban(ip_addr, 0);
+        bincode::serialize(&true).map_err(Error::Bincode)
     }
     pub fn transaction(node: &mut Node, transaction: Transaction) -> Result<Vec<u8>, Error> {
         bincode::serialize(&{
@@ -450,7 +900,7 @@ pub mod i {
                 .pending_transactions_push(transaction, node.args.time_delta)
             {
                 Ok(()) => {
-                    if let Err(e) = node.p2p.gossipsub_publish("transaction", vec) {
+                    if let Err(e) = node.p2p.gossipsub_publish(Topic::Transaction, vec) {
                         error!(?e);
                     }
                     "success".to_string()
@@ -471,7 +921,7 @@ pub mod i {
                 .pending_stakes_push(stake, node.args.time_delta)
             {
                 Ok(()) => {
-                    if let Err(e) = node.p2p.gossipsub_publish("stake", vec) {
+                    if let Err(e) = node.p2p.gossipsub_publish(Topic::Stake, vec) {
                         error!(?e);
                     }
                     "success".to_string()
@@ -490,6 +940,9 @@ pub mod i {
     pub fn ticks(node: &mut Node) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.ticks).map_err(Error::Bincode)
     }
+    pub fn time(node: &mut Node) -> Result<Vec<u8>, Error> {
+        bincode::serialize(&node.clock.timestamp_millis()).map_err(Error::Bincode)
+    }
     pub fn tree_size(node: &mut Node) -> Result<Vec<u8>, Error> {
         bincode::serialize(&node.blockchain.tree.size()).map_err(Error::Bincode)
     }
@@ -540,3 +993,52 @@ pub mod i {
         bincode::serialize(&node.blockchain.forks.stable.stakers.len()).map_err(Error::Bincode)
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Args;
+    use blockchain::Blockchain;
+    use clap::Parser;
+    use clock::OffsetClock;
+    use clock::SharedClock;
+    use libp2p::identity::Keypair;
+    use p2p::diversity::Limits;
+    use p2p::Transports;
+    use p2p::NETWORK_TESTNET;
+    use p2p::P2P;
+    use tempdir::TempDir;
+    #[tokio::test]
+    async fn test_transaction_proof_not_found() {
+        let tempdir = TempDir::new("tofuri-db").unwrap();
+        let db = db::open_cf_descriptors(tempdir.path());
+        let clock = OffsetClock::default();
+        let p2p = P2P::new(
+            Keypair::generate_ed25519(),
+            Transports::default(),
+            NETWORK_TESTNET,
+            Limits::default(),
+            SharedClock::new(clock.clone()),
+            10000,
+        )
+        .await
+        .unwrap();
+        let blockchain = Blockchain::new(SharedClock::new(clock.clone()));
+        let mut node = Node::new(
+            db,
+            None,
+            Args::parse_from(["tofuri"]),
+            p2p,
+            blockchain,
+            clock,
+        );
+        let (api_client, mut api_server) = channel(1);
+        let (res, _) = tokio::join!(
+            e::transaction_proof(State(api_client), Path(hex::encode([0; 32]))),
+            async {
+                let request = api_server.rx.recv().await.unwrap();
+                accept(&mut node, request).await;
+            }
+        );
+        assert!(matches!(res, Err(StatusCode::NOT_FOUND)));
+    }
+}
@@ -1,11 +1,22 @@
 use crate::Node;
-use crate::SHARE_PEERS_MAX_LEN;
-use chrono::Utc;
+use crate::ScoreEvent;
+use crate::DIAL_BACKOFF;
+use crate::DIAL_BACKOFF_MAX;
+use crate::PEER_CANDIDATE_TIMEOUT;
+use crate::PEER_FORGET_AFTER;
+use crate::PEER_FORGET_FAILURES;
+use crate::SYNC_BODIES_MAX_LEN;
+use crate::SYNC_RANGE_MAX_FAILURES;
+use clock::Clock;
 use fork::BLOCK_TIME;
-use multiaddr::ToMultiaddr;
+use libp2p::Multiaddr;
+use libp2p::PeerId;
+use multiaddr::ToIpAddr;
 use p2p::behaviour::Request;
+use p2p::diversity::Direction;
 use p2p::ratelimit::Endpoint;
 use rand::prelude::*;
+use std::collections::HashSet;
 use std::net::IpAddr;
 use tokio::time::Duration;
 use tokio::time::Instant;
@@ -15,10 +26,10 @@ use tracing::error;
 use tracing::info;
 use tracing::instrument;
 use tracing::warn;
-pub fn at(period: Duration) -> Interval {
+pub fn at(clock: &impl Clock, period: Duration) -> Interval {
     let nanos = period.as_nanos() as u64;
-    let start = Instant::now()
-        + Duration::from_nanos(nanos - chrono::offset::Utc::now().timestamp_nanos() as u64 % nanos);
+    let millis = clock.timestamp_millis() as u64;
+    let start = Instant::now() + Duration::from_nanos(nanos - millis * 1_000_000 % nanos);
     let mut interval = tokio::time::interval_at(start, period);
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
     interval
@@ -30,59 +41,146 @@ pub fn interval_1s(node: &mut Node) {
 }
 #[instrument(skip_all, level = "debug")]
 pub fn interval_10s(node: &mut Node) {
-    dial_known(node)
+    dial_known(node);
+    dial_candidates(node);
 }
 #[instrument(skip_all, level = "debug")]
 pub fn interval_1m(node: &mut Node) {
     grow(node);
-    share(node);
-    dial_unknown(node);
-    node.p2p.ratelimit.counter.clear();
+    exchange(node);
+    status(node);
+    uptime(node);
+    random_walk(node);
+    node.network_time();
+    node.scores_prune();
+    node.scores_flush();
+    node.p2p
+        .ratelimit
+        .limiter
+        .prune(node.clock.timestamp_millis() as u64);
 }
 #[instrument(skip_all, level = "debug")]
 pub fn interval_10m(node: &mut Node) {
     checkpoint(node);
+    bootstrap(node);
 }
 #[instrument(skip_all, level = "debug")]
-fn dial_known(node: &mut Node) {
-    let vec = node.p2p.connections_known.clone().into_iter().collect();
-    dial(node, vec);
+fn bootstrap(node: &mut Node) {
+    if let Err(e) = node.p2p.swarm.behaviour_mut().kademlia.bootstrap() {
+        debug!(?e);
+    }
 }
 #[instrument(skip_all, level = "debug")]
-fn dial_unknown(node: &mut Node) {
-    let vec = node.p2p.connections_unknown.drain().collect();
-    dial(node, vec);
+fn random_walk(node: &mut Node) {
+    node.p2p
+        .swarm
+        .behaviour_mut()
+        .kademlia
+        .get_closest_peers(PeerId::random());
 }
 #[instrument(skip_all, level = "debug")]
-fn dial(node: &mut Node, vec: Vec<IpAddr>) {
-    for ip_addr in vec {
-        if node.p2p.connections.iter().any(|x| x.1 == &ip_addr) {
+fn dial_known(node: &mut Node) {
+    let peers = match db::peer::get_all(&node.db) {
+        Ok(x) => x,
+        Err(e) => return error!(?e),
+    };
+    let timestamp = node.clock.timestamp();
+    for (address, peer) in peers {
+        let (multiaddr, ip_addr) = match address
+            .parse::<Multiaddr>()
+            .ok()
+            .and_then(|x| x.ip_addr().map(|ip_addr| (x, ip_addr)))
+        {
+            Some(x) => x,
+            None => {
+                debug!(address, "Forget invalid");
+                node.book_delete(&address);
+                continue;
+            }
+        };
+        if node.p2p.addresses.values().any(|x| x == &multiaddr) {
+            continue;
+        }
+        if peer.stale(timestamp, PEER_FORGET_FAILURES, PEER_FORGET_AFTER) {
+            debug!(address, "Forget");
+            node.book_delete(&address);
+            continue;
+        }
+        if !peer.dialable(timestamp, DIAL_BACKOFF, DIAL_BACKOFF_MAX) {
+            continue;
+        }
+        if node.banned(ip_addr) {
             continue;
         }
-        debug!(?ip_addr, "Dial");
-        let _ = node.p2p.swarm.dial(ip_addr.multiaddr(node.args.testnet));
+        if !node.p2p.access.permits_dial(&ip_addr) {
+            continue;
+        }
+        if !node.p2p.access.allows_ip(&ip_addr) {
+            if let Err(reject) = node.p2p.diversity.check(&ip_addr, Direction::Outbound) {
+                debug!(?multiaddr, ?reject, "Skip dial");
+                continue;
+            }
+        }
+        debug!(?multiaddr, "Dial");
+        if let Err(e) = node.p2p.swarm.dial(multiaddr.clone()) {
+            debug!(?e);
+            node.book_failure(&multiaddr);
+        }
     }
 }
 #[instrument(skip_all, level = "debug")]
-fn share(node: &mut Node) {
-    let mut vec: Vec<&IpAddr> = node.p2p.connections.values().collect();
-    if vec.is_empty() {
-        return;
-    }
-    vec.shuffle(&mut thread_rng());
-    vec.truncate(SHARE_PEERS_MAX_LEN);
-    debug!(?vec, "Share");
-    if let Err(e) = node
-        .p2p
-        .gossipsub_publish("peers", bincode::serialize(&vec).unwrap())
-    {
-        error!(?e);
+fn dial_candidates(node: &mut Node) {
+    let timestamp = node.clock.timestamp();
+    node.candidates.retain(|_, (_, dialed)| match dialed {
+        Some(x) => timestamp.saturating_sub(*x) < PEER_CANDIDATE_TIMEOUT,
+        None => true,
+    });
+    let multiaddrs: Vec<Multiaddr> = node
+        .candidates
+        .iter()
+        .filter(|(_, (_, dialed))| dialed.is_none())
+        .map(|(multiaddr, _)| multiaddr.clone())
+        .collect();
+    for multiaddr in multiaddrs {
+        if let Some(x) = node.candidates.get_mut(&multiaddr) {
+            x.1 = Some(timestamp);
+        }
+        let ip_addr = match multiaddr.ip_addr() {
+            Some(x) => x,
+            None => continue,
+        };
+        if node.p2p.addresses.values().any(|x| x == &multiaddr)
+            || node.banned(ip_addr)
+            || !node.p2p.access.permits_dial(&ip_addr)
+            || (!node.p2p.access.allows_ip(&ip_addr)
+                && node
+                    .p2p
+                    .diversity
+                    .check(&ip_addr, Direction::Outbound)
+                    .is_err())
+        {
+            continue;
+        }
+        debug!(?multiaddr, "Dial candidate");
+        if let Err(e) = node.p2p.swarm.dial(multiaddr.clone()) {
+            debug!(?e);
+            node.candidates.remove(&multiaddr);
+        }
     }
 }
 #[instrument(skip_all, level = "debug")]
+fn exchange(node: &mut Node) {
+    let peer_id = match node.p2p.statuses.keys().choose(&mut thread_rng()) {
+        Some(x) => *x,
+        None => return,
+    };
+    debug!(?peer_id, "Exchange");
+    node.p2p.send_request(&peer_id, Request::Peers);
+}
+#[instrument(skip_all, level = "debug")]
 fn grow(node: &mut Node) {
     let timestamp = {
-        let timestamp = Utc::now().timestamp() as u32;
+        let timestamp = node.clock.timestamp();
         timestamp - (timestamp % BLOCK_TIME)
     };
     let blockchain = &mut node.blockchain;
@@ -114,39 +212,85 @@ fn grow(node: &mut Node) {
     let block = node
         .blockchain
         .forge_block(&node.db, key, timestamp, node.args.trust);
-    if let Err(e) = node
-        .p2p
-        .gossipsub_publish("block", bincode::serialize(&block).unwrap())
-    {
-        error!(?e);
-    }
+    node.publish_block(&block, true);
 }
 #[instrument(skip_all, level = "debug")]
 fn sync_request(node: &mut Node) {
-    if node.blockchain.forks.unstable.latest_block.timestamp
-        >= Utc::now().timestamp() as u32 - BLOCK_TIME
+    if node.blockchain.forks.unstable.latest_block.timestamp >= node.clock.timestamp() - BLOCK_TIME
     {
         return;
     }
-    let peer_id = match node.p2p.swarm.connected_peers().choose(&mut thread_rng()) {
-        Some(x) => *x,
-        None => return,
-    };
-    let ip_addr = match node.p2p.connections.get(&peer_id) {
-        Some(x) => *x,
-        None => return,
-    };
-    if node.p2p.ratelimit.timeout.has(ip_addr, Endpoint::Response) {
+    let missing = node.blockchain.pending_headers_missing();
+    if missing.is_empty() {
+        let height = node.blockchain.height();
+        let mut peer_ids: Vec<PeerId> = node
+            .p2p
+            .statuses
+            .iter()
+            .filter(|(_, status)| status.height > height)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        if peer_ids.is_empty() {
+            peer_ids = node.p2p.swarm.connected_peers().cloned().collect();
+        }
+        let peer_id = match peer_ids.choose(&mut thread_rng()) {
+            Some(x) => *x,
+            None => return,
+        };
+        let ip_addr = match node.p2p.connections.get(&peer_id) {
+            Some(x) => *x,
+            None => return,
+        };
+        if node.p2p.ratelimit.timeout.has(ip_addr, Endpoint::Response) {
+            return;
+        }
+        node.p2p
+            .send_request(&peer_id, Request::Headers(node.blockchain.height()));
         return;
     }
-    node.p2p
-        .swarm
-        .behaviour_mut()
-        .request_response
-        .send_request(
-            &peer_id,
-            Request(bincode::serialize(&(node.blockchain.height())).unwrap()),
-        );
+    if node.p2p.scheduler.failures() >= SYNC_RANGE_MAX_FAILURES {
+        warn!(missing = missing.len(), "Sync stalled");
+        node.pending_headers_clear();
+        return;
+    }
+    let mut peer_ids: Vec<PeerId> = node
+        .p2p
+        .connections
+        .iter()
+        .filter(|(_, ip_addr)| {
+            !node
+                .p2p
+                .ratelimit
+                .timeout
+                .has(**ip_addr, Endpoint::Response)
+        })
+        .map(|(peer_id, _)| *peer_id)
+        .collect();
+    peer_ids.shuffle(&mut thread_rng());
+    let timestamp = node.clock.timestamp();
+    for (peer_id, hashes) in
+        node.p2p
+            .scheduler
+            .schedule(&missing, &peer_ids, SYNC_BODIES_MAX_LEN, timestamp)
+    {
+        node.p2p.send_request(&peer_id, Request::Bodies(hashes));
+    }
+}
+#[instrument(skip_all, level = "debug")]
+fn status(node: &mut Node) {
+    let status = node.status();
+    let peer_ids: Vec<PeerId> = node.p2p.swarm.connected_peers().cloned().collect();
+    for peer_id in peer_ids {
+        node.p2p
+            .send_request(&peer_id, Request::Status(status.clone()));
+    }
+}
+#[instrument(skip_all, level = "debug")]
+fn uptime(node: &mut Node) {
+    let vec: HashSet<IpAddr> = node.p2p.connections.values().cloned().collect();
+    for ip_addr in vec {
+        node.score(ip_addr, ScoreEvent::Uptime);
+    }
 }
 #[instrument(skip_all, level = "debug")]
 fn checkpoint(node: &mut Node) {
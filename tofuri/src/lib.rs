@@ -1,36 +1,410 @@
 pub mod api;
 pub mod interval;
 pub mod swarm;
+use block::Block;
+use block::CompactBlock;
 use blockchain::Blockchain;
 use clap::Parser;
+use clock::Clock;
+use clock::OffsetClock;
+use clock::NETWORK_TIME_MAX_OFFSET;
+use db::peer::PeerDB;
+use db::peer::Source;
+use db::score::ScoreDB;
 use key::Key;
+use libp2p::identity::DecodingError;
+use libp2p::identity::Keypair;
+use libp2p::Multiaddr;
+use libp2p::PeerId;
+use multiaddr::ToIpAddr;
+use p2p::access;
+use p2p::access::Access;
+use p2p::behaviour::Status;
+use p2p::topic::Topic;
 use p2p::P2P;
+use p2p::P2P_DIVERSITY_IPV4_16;
+use p2p::P2P_DIVERSITY_IPV4_24;
+use p2p::P2P_DIVERSITY_IPV6_32;
+use p2p::P2P_DIVERSITY_IPV6_48;
+use p2p::P2P_DIVERSITY_OUTBOUND_RESERVED;
+use p2p::STATUS_VERSION;
+use rand::rngs::OsRng;
 use rocksdb::DB;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::net::IpAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
 pub const CARGO_PKG_NAME: &str = env!("CARGO_PKG_NAME");
 pub const CARGO_PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const CARGO_PKG_REPOSITORY: &str = env!("CARGO_PKG_REPOSITORY");
 pub const GIT_HASH: &str = env!("GIT_HASH");
 pub const SHARE_PEERS_MAX_LEN: usize = 100;
+pub const SHARE_PEERS_SAMPLE_LEN: usize = 10;
+pub const HISTORY_PAGE_LEN: usize = 100;
+pub const SYNC_BODIES_MAX_LEN: usize = 100;
+pub const SYNC_RANGE_MAX_FAILURES: usize = 3;
+pub const SCORE_BAN: i64 = -100;
+pub const SCORE_BAN_DURATION: u32 = 86400;
+pub const SCORE_IDLE_TTL: u32 = 604800;
+pub const DIAL_BACKOFF: u32 = 10;
+pub const DIAL_BACKOFF_MAX: u32 = 86400;
+pub const PEER_FORGET_FAILURES: u32 = 10;
+pub const PEER_FORGET_AFTER: u32 = 604800;
+pub const PEER_BOOK_MAX_LEN: usize = 1000;
+pub const PEER_CANDIDATES_MAX_LEN: usize = 100;
+pub const PEER_CANDIDATE_TIMEOUT: u32 = 60;
+pub const CLOCK_DRIFT_WARN: i64 = 1000;
+#[derive(Debug)]
+pub enum Error {
+    IdentityPassword,
+    Identity(DecodingError),
+}
+#[derive(Debug, Clone, Copy)]
+pub enum ScoreEvent {
+    Invalid,
+    Ratelimited,
+    Useful,
+    Uptime,
+}
 pub struct Node {
     pub db: DB,
     pub key: Option<Key>,
     pub args: Args,
     pub p2p: P2P,
     pub blockchain: Blockchain,
+    pub clock: OffsetClock,
     pub ticks: usize,
+    pub scores: HashMap<IpAddr, ScoreDB>,
+    scores_dirty: HashSet<IpAddr>,
+    scores_seen: HashMap<IpAddr, u32>,
+    pub candidates: HashMap<Multiaddr, (Source, Option<u32>)>,
+    book_len: usize,
+    pub headers_peer: Option<PeerId>,
+    pub orphans: HashMap<[u8; 32], IpAddr>,
 }
 impl Node {
-    pub fn new(db: DB, key: Option<Key>, args: Args, p2p: P2P, blockchain: Blockchain) -> Node {
+    pub fn new(
+        db: DB,
+        key: Option<Key>,
+        args: Args,
+        p2p: P2P,
+        blockchain: Blockchain,
+        clock: OffsetClock,
+    ) -> Node {
         Node {
             db,
             key,
             args,
             p2p,
             blockchain,
+            clock,
             ticks: 0,
+            scores: HashMap::new(),
+            scores_dirty: HashSet::new(),
+            scores_seen: HashMap::new(),
+            candidates: HashMap::new(),
+            book_len: 0,
+            headers_peer: None,
+            orphans: HashMap::new(),
+        }
+    }
+    pub fn book_load(&mut self) -> Result<(), db::Error> {
+        self.book_len = db::peer::get_all(&self.db)?.len();
+        Ok(())
+    }
+    pub fn scores_load(&mut self) -> Result<(), db::Error> {
+        self.scores = db::score::get_all(&self.db)?.into_iter().collect();
+        let timestamp = self.clock.timestamp();
+        self.scores_seen = self.scores.keys().map(|x| (*x, timestamp)).collect();
+        Ok(())
+    }
+    pub fn scores_flush(&mut self) {
+        for ip_addr in self.scores_dirty.drain() {
+            let score = match self.scores.get(&ip_addr) {
+                Some(x) => x,
+                None => continue,
+            };
+            if let Err(e) = db::score::put(&self.db, &ip_addr, score) {
+                error!(?e);
+            }
+        }
+    }
+    pub fn scores_prune(&mut self) {
+        let timestamp = self.clock.timestamp();
+        let scores_seen = &self.scores_seen;
+        let mut pruned = vec![];
+        self.scores.retain(|ip_addr, score| {
+            let seen = scores_seen.get(ip_addr).copied().unwrap_or_default();
+            if timestamp.saturating_sub(seen) < SCORE_IDLE_TTL
+                || !score.neutral()
+                || score.banned(timestamp)
+            {
+                return true;
+            }
+            pruned.push(*ip_addr);
+            false
+        });
+        for ip_addr in pruned {
+            self.scores_seen.remove(&ip_addr);
+            self.scores_dirty.remove(&ip_addr);
+            if let Err(e) = db::score::delete(&self.db, &ip_addr) {
+                error!(?e);
+            }
+        }
+    }
+    pub fn orphans_prune(&mut self) {
+        let blockchain = &self.blockchain;
+        let mut stale = vec![];
+        self.orphans.retain(|hash, ip_addr| {
+            if blockchain.orphans_contains(hash) {
+                return true;
+            }
+            if blockchain.tree.get(hash).is_none() && blockchain.pending_block(hash).is_none() {
+                stale.push(*ip_addr);
+            }
+            false
+        });
+        for ip_addr in stale {
+            self.score(ip_addr, ScoreEvent::Invalid);
+        }
+    }
+    pub fn ban(&mut self, ip_addr: IpAddr, until: u32) {
+        self.scores.entry(ip_addr).or_default().ban(until);
+        self.scores_dirty.insert(ip_addr);
+        self.scores_seen.insert(ip_addr, self.clock.timestamp());
+    }
+    pub fn score(&mut self, ip_addr: IpAddr, event: ScoreEvent) {
+        let timestamp = self.clock.timestamp();
+        let score = self.scores.entry(ip_addr).or_default();
+        match event {
+            ScoreEvent::Invalid => score.invalid += 1,
+            ScoreEvent::Ratelimited => score.ratelimited += 1,
+            ScoreEvent::Useful => score.useful += 1,
+            ScoreEvent::Uptime => score.uptime += 1,
+        }
+        let ban = score.score() <= SCORE_BAN && !score.banned(timestamp);
+        if ban {
+            warn!(?ip_addr, score = score.score(), "Ban");
+            score.ban(timestamp + SCORE_BAN_DURATION);
+        }
+        self.scores_dirty.insert(ip_addr);
+        self.scores_seen.insert(ip_addr, timestamp);
+        if ban {
+            self.disconnect(ip_addr);
+        }
+    }
+    pub fn routable(&self, multiaddr: &Multiaddr) -> bool {
+        match multiaddr.ip_addr() {
+            Some(ip_addr) if self.args.psk_file.is_some() => {
+                !ip_addr.is_loopback() && !ip_addr.is_unspecified()
+            }
+            Some(ip_addr) => multiaddr::is_global(&ip_addr),
+            None => false,
+        }
+    }
+    pub fn book_insert(&mut self, multiaddr: &Multiaddr, source: Source) -> bool {
+        if self.book_len >= PEER_BOOK_MAX_LEN && !matches!(source, Source::Config | Source::Api) {
+            return false;
+        }
+        let address = multiaddr.to_string();
+        match db::peer::get(&self.db, &address) {
+            Ok(None) => {}
+            Ok(Some(_)) => return false,
+            Err(e) => {
+                error!(?e);
+                return false;
+            }
+        }
+        if let Err(e) = db::peer::put(&self.db, &address, &PeerDB::new(source)) {
+            error!(?e);
+            return false;
+        }
+        self.book_len += 1;
+        true
+    }
+    pub fn book_success(&mut self, multiaddr: &Multiaddr, source: Source) {
+        let address = multiaddr.to_string();
+        let (mut peer, new) = match db::peer::get(&self.db, &address) {
+            Ok(Some(x)) => (x, false),
+            Ok(None) => (PeerDB::new(source), true),
+            Err(e) => return error!(?e),
+        };
+        peer.last_success = self.clock.timestamp();
+        peer.failures = 0;
+        if let Err(e) = db::peer::put(&self.db, &address, &peer) {
+            return error!(?e);
+        }
+        if new {
+            self.book_len += 1;
+        }
+    }
+    pub fn book_delete(&mut self, address: &str) {
+        if let Err(e) = db::peer::delete(&self.db, address) {
+            return error!(?e);
+        }
+        self.book_len = self.book_len.saturating_sub(1);
+    }
+    pub fn candidate_insert(&mut self, multiaddr: Multiaddr, source: Source) -> bool {
+        if self.candidates.len() >= PEER_CANDIDATES_MAX_LEN
+            || self.candidates.contains_key(&multiaddr)
+        {
+            return false;
+        }
+        match db::peer::get(&self.db, &multiaddr.to_string()) {
+            Ok(None) => {}
+            Ok(Some(_)) => return false,
+            Err(e) => {
+                error!(?e);
+                return false;
+            }
+        }
+        self.candidates.insert(multiaddr, (source, None));
+        true
+    }
+    pub fn book_failure(&mut self, multiaddr: &Multiaddr) {
+        let address = multiaddr.to_string();
+        let mut peer = match db::peer::get(&self.db, &address) {
+            Ok(Some(x)) => x,
+            Ok(None) => return,
+            Err(e) => return error!(?e),
+        };
+        peer.last_failure = self.clock.timestamp();
+        peer.failures += 1;
+        if let Err(e) = db::peer::put(&self.db, &address, &peer) {
+            error!(?e);
+        }
+    }
+    pub fn peer_score(&self, ip_addr: &IpAddr) -> i64 {
+        self.scores
+            .get(ip_addr)
+            .map(|x| x.score())
+            .unwrap_or_default()
+    }
+    pub fn banned(&self, ip_addr: IpAddr) -> bool {
+        self.scores
+            .get(&ip_addr)
+            .map(|x| x.banned(self.clock.timestamp()))
+            .unwrap_or_default()
+    }
+    pub fn disconnect(&mut self, ip_addr: IpAddr) {
+        let peer_ids: Vec<PeerId> = self
+            .p2p
+            .connections
+            .iter()
+            .filter(|(_, a)| **a == ip_addr)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in peer_ids {
+            let _ = self.p2p.swarm.disconnect_peer_id(peer_id);
         }
     }
+    pub fn access_reload(&mut self) -> Result<(), access::Error> {
+        let path = match &self.args.access_list {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        self.p2p.access = Access::load(path)?;
+        info!(
+            allow = self.p2p.access.allow.len(),
+            deny = self.p2p.access.deny.len(),
+            "Access list loaded"
+        );
+        let peer_ids: Vec<PeerId> = self
+            .p2p
+            .connections
+            .iter()
+            .filter(|(peer_id, ip_addr)| !self.p2p.access.permits(ip_addr, peer_id))
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in peer_ids {
+            warn!(?peer_id, "Not permitted");
+            let _ = self.p2p.swarm.disconnect_peer_id(peer_id);
+        }
+        Ok(())
+    }
+    pub fn status(&self) -> Status {
+        Status {
+            version: STATUS_VERSION,
+            network: self.p2p.network,
+            genesis: self.blockchain.hash_by_height(1).unwrap_or_default(),
+            height: self.blockchain.height(),
+            hash: self
+                .blockchain
+                .hash_by_height(self.blockchain.height())
+                .unwrap_or_default(),
+            timestamp: self.clock.timestamp_millis(),
+            compact_blocks: true,
+        }
+    }
+    pub fn publish_block(&mut self, block: &Block, compact: bool) {
+        if compact {
+            if let Err(e) = self.p2p.gossipsub_publish(
+                Topic::CompactBlock,
+                bincode::serialize(&CompactBlock::from(block)).unwrap(),
+            ) {
+                error!(?e);
+            }
+        }
+        if self.p2p.compact_blocks_supported() {
+            return;
+        }
+        if let Err(e) = self
+            .p2p
+            .gossipsub_publish(Topic::Block, bincode::serialize(block).unwrap())
+        {
+            error!(?e);
+        }
+    }
+    pub fn pending_headers_clear(&mut self) {
+        self.blockchain.pending_headers_clear();
+        self.p2p.scheduler.clear();
+        self.headers_peer = None;
+    }
+    pub fn network_time(&mut self) {
+        let median = match self.p2p.network_time.median() {
+            Some(x) => x,
+            None => return,
+        };
+        let samples = self.p2p.network_time.len();
+        if median.abs() > self.args.max_drift {
+            warn!(median, samples, "Clock drift");
+        }
+        if !self.args.network_time {
+            return;
+        }
+        if median.abs() > NETWORK_TIME_MAX_OFFSET {
+            warn!(median, samples, "Network time offset capped");
+        }
+        self.clock
+            .set_offset(median.clamp(-NETWORK_TIME_MAX_OFFSET, NETWORK_TIME_MAX_OFFSET));
+    }
+}
+pub fn identity_path(db_path: &str, network: u8) -> PathBuf {
+    PathBuf::from(format!("{}-identity-{}", db_path, network))
+}
+pub fn identity(path: impl AsRef<Path>, pwd: &str, rotate: bool) -> Result<Keypair, Error> {
+    let path = path.as_ref();
+    if path.exists() && !rotate {
+        let encrypted = key_store::read(path);
+        let secret = encryption::decrypt(&encrypted, pwd).ok_or(Error::IdentityPassword)?;
+        return Keypair::ed25519_from_bytes(secret).map_err(Error::Identity);
+    }
+    let keypair = Keypair::generate_ed25519();
+    let secret: [u8; 32] = keypair
+        .clone()
+        .try_into_ed25519()
+        .unwrap()
+        .secret()
+        .as_ref()
+        .try_into()
+        .unwrap();
+    key_store::write_secret(&mut OsRng, &secret, path, pwd);
+    info!(?path, "Generated identity");
+    Ok(keypair)
 }
 #[derive(Parser, Debug, Clone)]
 #[clap(version, about, long_about = None)]
@@ -47,6 +421,10 @@ pub struct Args {
     #[clap(long, env = "TESTNET")]
     pub testnet: bool,
 
+    /// Keep balances and stakes of every height
+    #[clap(long, env = "ARCHIVE")]
+    pub archive: bool,
+
     /// Trust fork after blocks
     #[clap(long, env = "TRUST", default_value_t = 2)]
     pub trust: usize,
@@ -55,22 +433,86 @@ pub struct Args {
     #[clap(long, env = "TIME_DELTA", default_value_t = 1)]
     pub time_delta: u32,
 
+    /// Adjust the local clock by the median offset of peers
+    #[clap(long, env = "NETWORK_TIME")]
+    pub network_time: bool,
+
+    /// Warn when the clock differs from peers by more milliseconds
+    #[clap(long, env = "MAX_DRIFT", default_value_t = CLOCK_DRIFT_WARN)]
+    pub max_drift: i64,
+
     /// Timeout
     #[clap(long, env = "TIMEOUT", default_value_t = 10000)]
     pub timeout: u64,
 
-    /// IpAddr to dial
+    /// Multiaddr or IpAddr to dial
     #[clap(long, env = "PEER")]
-    pub peer: Option<IpAddr>,
+    pub peer: Option<String>,
+
+    /// Port to listen on
+    #[clap(long, env = "PORT")]
+    pub port: Option<u16>,
+
+    /// Enable the QUIC transport on the same port over UDP
+    #[clap(long, env = "QUIC")]
+    pub quic: bool,
+
+    /// Enable the WebSocket transport
+    #[clap(long, env = "WEBSOCKET")]
+    pub websocket: bool,
+
+    /// Port to listen on for WebSocket connections
+    #[clap(long, env = "WEBSOCKET_PORT")]
+    pub websocket_port: Option<u16>,
+
+    /// Relay connections for peers behind NAT
+    #[clap(long, env = "RELAY")]
+    pub relay: bool,
+
+    /// File with allow and deny rules for IPs, CIDRs and PeerIds
+    #[clap(long, env = "ACCESS_LIST")]
+    pub access_list: Option<String>,
+
+    /// Pre-shared key file of a private network
+    #[clap(long, env = "PSK_FILE")]
+    pub psk_file: Option<String>,
 
     /// Swarm connection limits
     #[clap(long, env = "MAX_ESTABLISHED")]
     pub max_established: Option<u32>,
 
+    /// Connections per IPv4 /16 and direction
+    #[clap(long, env = "MAX_IPV4_16", default_value_t = P2P_DIVERSITY_IPV4_16)]
+    pub max_ipv4_16: usize,
+
+    /// Connections per IPv4 /24 and direction
+    #[clap(long, env = "MAX_IPV4_24", default_value_t = P2P_DIVERSITY_IPV4_24)]
+    pub max_ipv4_24: usize,
+
+    /// Connections per IPv6 /32 and direction
+    #[clap(long, env = "MAX_IPV6_32", default_value_t = P2P_DIVERSITY_IPV6_32)]
+    pub max_ipv6_32: usize,
+
+    /// Connections per IPv6 /48 and direction
+    #[clap(long, env = "MAX_IPV6_48", default_value_t = P2P_DIVERSITY_IPV6_48)]
+    pub max_ipv6_48: usize,
+
+    /// Connection slots reserved for outbound connections
+    #[clap(long, env = "OUTBOUND_RESERVED", default_value_t = P2P_DIVERSITY_OUTBOUND_RESERVED)]
+    pub outbound_reserved: usize,
+
     /// Secret key
     #[clap(long, env = "SECRET")]
     pub secret: Option<String>,
 
+    /// Password of the stored libp2p identity, empty by default which leaves it unprotected
+    #[clap(long, env = "IDENTITY_PWD", default_value = "")]
+    pub identity_pwd: String,
+
+    /// Replace the stored libp2p identity with a new one
+    #[clap(long, env = "ROTATE_IDENTITY")]
+    pub rotate_identity: bool,
+
     /// API Endpoint
     #[clap(long, env = "API", default_value = "[::]:2021")]
     pub api: String,
@@ -83,3 +525,23 @@ pub struct Args {
     #[clap(long, env = "WITHOUT_TIME")]
     pub without_time: bool,
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    #[test]
+    fn test_identity() {
+        let tempdir = TempDir::new("tofuri-identity").unwrap();
+        let path = tempdir.path().join("identity");
+        let keypair = identity(&path, "pwd", false).unwrap();
+        assert_eq!(
+            identity(&path, "pwd", false).unwrap().public(),
+            keypair.public()
+        );
+        assert!(matches!(
+            identity(&path, "", false),
+            Err(Error::IdentityPassword)
+        ));
+        assert_ne!(identity_path("db", 0), identity_path("db", 1));
+    }
+}
@@ -2,12 +2,25 @@ use address::public;
 use address::secret;
 use blockchain::Blockchain;
 use clap::Parser;
+use clock::OffsetClock;
+use clock::SharedClock;
 use colored::*;
+use db::peer::Source;
 use key::Key;
 use libp2p::futures::StreamExt;
+use libp2p::identity::Keypair;
+use libp2p::pnet::PreSharedKey;
 use multiaddr::ToMultiaddr;
+use multiaddr::MAINNET_PORT;
+use multiaddr::MAINNET_WEBSOCKET_PORT;
+use multiaddr::TESTNET_PORT;
+use multiaddr::TESTNET_WEBSOCKET_PORT;
+use p2p::diversity::Limits;
+use p2p::Transports;
+use p2p::NETWORK_MAINNET;
+use p2p::NETWORK_TESTNET;
 use p2p::P2P;
-use std::collections::HashSet;
+use std::fs;
 use std::net::IpAddr;
 use std::time::Duration;
 use tempdir::TempDir;
@@ -17,6 +30,7 @@ use tofuri::swarm;
 use tofuri::Args;
 use tofuri::Node;
 use tracing::debug;
+use tracing::error;
 use tracing::info;
 use tracing::warn;
 use tracing_subscriber::filter::LevelFilter;
@@ -45,11 +59,11 @@ async fn main() {
     if args.testnet {
         warn!("{}", "RUNNING ON TESTNET!".yellow());
     }
+    let (api_client, mut api_server) = api::channel(1);
     if let Ok(addr) = args.control.parse() {
-        ::control::spawn(handle, &addr);
+        ::control::spawn_with(handle, &addr, api::control(api_client.clone()));
         info!(?addr, "control server listening on");
     }
-    let (api_client, mut api_server) = api::channel(1);
     if let Ok(addr) = args.api.parse() {
         api::spawn(api_client, &addr);
         info!(?addr, "api server listening on");
@@ -75,30 +89,136 @@ async fn main() {
             .to_string(),
         false => "./tofuri-db".to_string(),
     };
-    let db = db::open_cf_descriptors(path);
-    let mut connections_known = HashSet::new();
-    if let Some(ip_addr) = args.peer {
-        connections_known.insert(ip_addr);
+    let db = db::open_cf_descriptors(&path);
+    let network = match args.testnet {
+        true => NETWORK_TESTNET,
+        false => NETWORK_MAINNET,
+    };
+    let local_key = match args.tempdb {
+        true => Keypair::generate_ed25519(),
+        false => {
+            if args.identity_pwd.is_empty() {
+                warn!("Identity is stored without a password");
+            }
+            match tofuri::identity(
+                tofuri::identity_path(&path, network),
+                &args.identity_pwd,
+                args.rotate_identity,
+            ) {
+                Ok(keypair) => keypair,
+                Err(e) => {
+                    error!(?e, "Identity");
+                    return;
+                }
+            }
+        }
+    };
+    info!(peer_id = %local_key.public().to_peer_id(), "Identity");
+    let psk = args.psk_file.as_ref().map(|path| {
+        fs::read_to_string(path)
+            .unwrap()
+            .parse::<PreSharedKey>()
+            .unwrap()
+    });
+    if let Some(psk) = &psk {
+        info!(fingerprint = %psk.fingerprint(), "Private network");
+        if args.quic {
+            warn!("QUIC is disabled in a private network");
+        }
     }
-    let peers = db::peer::get_all(&db).unwrap();
-    for ip_addr in peers {
-        connections_known.insert(ip_addr);
+    let transports = Transports {
+        quic: args.quic,
+        websocket: args.websocket,
+        relay: args.relay,
+        psk,
+    };
+    let clock = OffsetClock::default();
+    let p2p = P2P::new(
+        local_key,
+        transports,
+        network,
+        Limits {
+            max_established: args.max_established,
+            ipv4_16: args.max_ipv4_16,
+            ipv4_24: args.max_ipv4_24,
+            ipv6_32: args.max_ipv6_32,
+            ipv6_48: args.max_ipv6_48,
+            outbound_reserved: args.outbound_reserved,
+        },
+        SharedClock::new(clock.clone()),
+        args.timeout,
+    )
+    .await
+    .unwrap();
+    let blockchain = Blockchain::new(SharedClock::new(clock.clone()));
+    let mut node = Node::new(db, key, args.clone(), p2p, blockchain, clock);
+    match db::transaction_block::backfill(&node.db) {
+        Ok(0) => {}
+        Ok(len) => info!(len, "Indexed transactions"),
+        Err(e) => error!(?e),
     }
-    let p2p = P2P::new(args.max_established, args.timeout, connections_known)
-        .await
+    node.blockchain
+        .load(&node.db, node.args.trust, node.args.archive)
         .unwrap();
-    let blockchain = Blockchain::default();
-    let mut node = Node::new(db, key, args.clone(), p2p, blockchain);
-    node.blockchain.load(&node.db, node.args.trust).unwrap();
+    node.access_reload().unwrap();
+    node.scores_load().unwrap();
+    let testnet = node.args.testnet;
+    match db::peer::migrate(&node.db, |ip_addr, port| {
+        match port {
+            Some(port) => ip_addr.multiaddr_port(port),
+            None => ip_addr.multiaddr(testnet),
+        }
+        .to_string()
+    }) {
+        Ok(0) => {}
+        Ok(len) => info!(len, "Migrated peers"),
+        Err(e) => error!(?e),
+    }
+    match db::peer::prune(&node.db) {
+        Ok(0) => {}
+        Ok(len) => info!(len, "Pruned invalid peers"),
+        Err(e) => error!(?e),
+    }
+    node.book_load().unwrap();
+    if let Some(peer) = node.args.peer.clone() {
+        match multiaddr::parse(&peer, node.args.testnet) {
+            Some(multiaddr) => {
+                node.book_insert(&multiaddr, Source::Config);
+            }
+            None => error!(peer, "Invalid peer"),
+        }
+    }
     let ip_addr = "0.0.0.0".parse::<IpAddr>().unwrap();
+    let port = match (args.port, args.testnet) {
+        (Some(port), _) => port,
+        (None, true) => TESTNET_PORT,
+        (None, false) => MAINNET_PORT,
+    };
     node.p2p
         .swarm
-        .listen_on(ip_addr.multiaddr(args.testnet))
+        .listen_on(ip_addr.multiaddr_port(port))
         .unwrap();
-    let mut interval_1s = interval::at(Duration::from_secs(1));
-    let mut interval_10s = interval::at(Duration::from_secs(10));
-    let mut interval_1m = interval::at(Duration::from_secs(60));
-    let mut interval_10m = interval::at(Duration::from_secs(600));
+    if args.quic {
+        node.p2p
+            .swarm
+            .listen_on(ip_addr.multiaddr_quic(port))
+            .unwrap();
+    }
+    if args.websocket {
+        let port = match (args.websocket_port, args.testnet) {
+            (Some(port), _) => port,
+            (None, true) => TESTNET_WEBSOCKET_PORT,
+            (None, false) => MAINNET_WEBSOCKET_PORT,
+        };
+        node.p2p
+            .swarm
+            .listen_on(ip_addr.multiaddr_websocket(port))
+            .unwrap();
+    }
+    let mut interval_1s = interval::at(&node.clock, Duration::from_secs(1));
+    let mut interval_10s = interval::at(&node.clock, Duration::from_secs(10));
+    let mut interval_1m = interval::at(&node.clock, Duration::from_secs(60));
+    let mut interval_10m = interval::at(&node.clock, Duration::from_secs(600));
     loop {
         node.ticks += 1;
         tokio::select! {
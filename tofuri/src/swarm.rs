@@ -1,26 +1,57 @@
 use crate::Node;
+use crate::ScoreEvent;
+use crate::PEER_FORGET_AFTER;
 use crate::SHARE_PEERS_MAX_LEN;
+use crate::SHARE_PEERS_SAMPLE_LEN;
+use crate::SYNC_BODIES_MAX_LEN;
 use block::Block;
+use block::CompactBlock;
+use block::Header;
+use block::Missing;
+use clock::Clock;
 use db;
+use db::peer::Source;
+use libp2p::autonat;
+use libp2p::autonat::NatStatus;
 use libp2p::core::connection::ConnectedPoint;
+use libp2p::dcutr;
 use libp2p::gossipsub;
 use libp2p::gossipsub::MessageAcceptance;
 use libp2p::gossipsub::MessageId;
+use libp2p::identify;
+use libp2p::kad::KademliaEvent;
+use libp2p::kad::QueryResult;
 use libp2p::mdns;
+use libp2p::relay;
 use libp2p::request_response;
+use libp2p::request_response::RequestId;
 use libp2p::request_response::ResponseChannel;
-use libp2p::swarm::derive_prelude::Either;
-use libp2p::swarm::ConnectionHandlerUpgrErr;
+use libp2p::swarm::DialError;
 use libp2p::swarm::SwarmEvent;
+use libp2p::swarm::THandlerErr;
+use libp2p::Multiaddr;
 use libp2p::PeerId;
 use multiaddr::ToIpAddr;
+use multiaddr::ToMultiaddr;
+use multiaddr::ToPort;
+use multiaddr::ToTransport;
+use multiaddr::Transport;
+use p2p::behaviour::Behaviour;
 use p2p::behaviour::OutEvent;
+use p2p::behaviour::PeerRecord;
+use p2p::behaviour::Refusal;
 use p2p::behaviour::Request;
 use p2p::behaviour::Response;
+use p2p::behaviour::Status;
+use p2p::diversity::Direction;
+use p2p::diversity::Reject;
 use p2p::ratelimit::Endpoint;
-use p2p::MAX_TRANSMIT_SIZE;
+use p2p::topic::Topic;
+use p2p::MAX_RESPONSE_SIZE;
+use p2p::PROTOCOL_NAME_KAD;
+use p2p::PROTOCOL_NAME_RELAY_HOP;
+use rand::prelude::*;
 use stake::Stake;
-use std::io::Error;
 use std::net::IpAddr;
 use std::num::NonZeroU32;
 use tracing::debug;
@@ -29,17 +60,7 @@ use tracing::info;
 use tracing::instrument;
 use tracing::warn;
 use transaction::Transaction;
-use void::Void;
-type Event = SwarmEvent<
-    OutEvent,
-    Either<
-        Either<
-            Either<Either<Either<Void, Error>, Void>, ConnectionHandlerUpgrErr<Error>>,
-            ConnectionHandlerUpgrErr<Error>,
-        >,
-        Void,
-    >,
->;
+type Event = SwarmEvent<OutEvent, THandlerErr<Behaviour>>;
 #[instrument(skip_all, level = "debug")]
 pub fn event(node: &mut Node, event: Event) {
     match event {
@@ -54,7 +75,16 @@ pub fn event(node: &mut Node, event: Event) {
             num_established,
             ..
         } => connection_closed(node, peer_id, num_established),
+        SwarmEvent::OutgoingConnectionError { error, .. } => outgoing_connection_error(node, error),
         SwarmEvent::Behaviour(OutEvent::Mdns(event)) => mdns(node, event),
+        SwarmEvent::Behaviour(OutEvent::Identify(identify::Event::Received { peer_id, info })) => {
+            identify(node, peer_id, info)
+        }
+        SwarmEvent::Behaviour(OutEvent::Kademlia(event)) => kademlia(node, event),
+        SwarmEvent::Behaviour(OutEvent::Autonat(event)) => autonat(node, event),
+        SwarmEvent::Behaviour(OutEvent::Relay(event)) => debug!(?event, "Relay"),
+        SwarmEvent::Behaviour(OutEvent::RelayClient(event)) => relay_client(node, event),
+        SwarmEvent::Behaviour(OutEvent::Dcutr(event)) => dcutr(event),
         SwarmEvent::Behaviour(OutEvent::Gossipsub(gossipsub::Event::Message {
             message_id,
             message,
@@ -68,10 +98,18 @@ pub fn event(node: &mut Node, event: Event) {
             request_response::Message::Request {
                 request, channel, ..
             } => sync_request(node, peer, request, channel),
-            request_response::Message::Response { response, .. } => {
-                sync_response(node, peer, response)
-            }
+            request_response::Message::Response {
+                request_id,
+                response,
+            } => sync_response(node, peer, request_id, response),
         },
+        SwarmEvent::Behaviour(OutEvent::RequestResponse(
+            request_response::Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            },
+        )) => outbound_failure(node, peer, request_id, error),
         _ => {}
     }
 }
@@ -82,39 +120,312 @@ fn connection_established(
     endpoint: ConnectedPoint,
     num_established: NonZeroU32,
 ) {
-    let ip_addr = match endpoint {
-        ConnectedPoint::Dialer { address, .. } => address.ip_addr().unwrap(),
-        ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr.ip_addr().unwrap(),
+    let relayed = endpoint.is_relayed();
+    if relayed && node.p2p.connections.contains_key(&peer_id) {
+        return;
+    }
+    let (ip_addr, address, direction) = match endpoint {
+        ConnectedPoint::Dialer { address, .. } => {
+            (address.ip_addr(), Some(address), Direction::Outbound)
+        }
+        ConnectedPoint::Listener {
+            local_addr,
+            send_back_addr,
+        } => match relayed {
+            true => (local_addr.ip_addr(), None, Direction::Inbound),
+            false => (send_back_addr.ip_addr(), None, Direction::Inbound),
+        },
+    };
+    let ip_addr = match ip_addr {
+        Some(x) => x,
+        None => {
+            warn!(?peer_id, "Connection without ip address");
+            let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+            return;
+        }
     };
-    node.p2p.connections_known.insert(ip_addr);
-    let _ = db::peer::put(&node.db, &ip_addr);
+    if node.banned(ip_addr) {
+        warn!(?ip_addr, "Banned");
+        let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+        return;
+    }
+    if !node.p2p.access.permits(&ip_addr, &peer_id) {
+        warn!(?ip_addr, ?peer_id, "Not permitted");
+        let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+        return;
+    }
+    if !diversity(node, peer_id, ip_addr, direction) {
+        let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+        return;
+    }
+    if let Some(address) = address.filter(|_| !relayed) {
+        let source = node
+            .candidates
+            .remove(&address)
+            .map(|x| x.0)
+            .unwrap_or_default();
+        node.book_success(&address, source);
+        node.p2p.addresses.insert(peer_id, address);
+    }
     // if let Some((previous_peer_id, _)) = node.p2p.connections.iter().find(|x| x.1 == &ip_addr) {
     // if previous_peer_id != &peer_id {
     // let _ = node.p2p.swarm.disconnect_peer_id(*previous_peer_id);
     // }
     // }
     node.p2p.connections.insert(peer_id, ip_addr);
-    info!(?ip_addr, num_established, "Connection established");
+    let status = node.status();
+    node.p2p.send_request(&peer_id, Request::Status(status));
+    info!(?ip_addr, relayed, num_established, "Connection established");
+}
+#[instrument(skip_all, level = "trace")]
+fn diversity(node: &mut Node, peer_id: PeerId, ip_addr: IpAddr, direction: Direction) -> bool {
+    node.p2p.diversity.remove(&peer_id);
+    if node.p2p.access.allows(&ip_addr, &peer_id) {
+        node.p2p.diversity.insert(peer_id, ip_addr, direction);
+        return true;
+    }
+    match node.p2p.diversity.check(&ip_addr, direction) {
+        Ok(()) => {}
+        Err(Reject::InboundFull) => {
+            let score = node.peer_score(&ip_addr);
+            let scores = &node.scores;
+            let evicted = node
+                .p2p
+                .diversity
+                .evict(|x| scores.get(x).map(|x| x.score()).unwrap_or_default());
+            match evicted {
+                Some((evicted_peer_id, evicted_ip_addr, evicted_score))
+                    if evicted_score < score =>
+                {
+                    info!(?evicted_ip_addr, evicted_score, ?ip_addr, score, "Evict");
+                    node.p2p.diversity.remove(&evicted_peer_id);
+                    let _ = node.p2p.swarm.disconnect_peer_id(evicted_peer_id);
+                }
+                _ => {
+                    warn!(?ip_addr, score, "Inbound full");
+                    return false;
+                }
+            }
+        }
+        Err(reject) => {
+            warn!(?ip_addr, ?direction, ?reject, "Subnet limit");
+            return false;
+        }
+    }
+    node.p2p.diversity.insert(peer_id, ip_addr, direction);
+    true
 }
 #[instrument(skip_all, level = "trace")]
 fn connection_closed(node: &mut Node, peer_id: PeerId, num_established: u32) {
-    let res = node.p2p.connections.remove(&peer_id);
-    let ip_addr = res.unwrap();
+    if num_established != 0 {
+        return;
+    }
+    node.p2p.relays.remove(&peer_id);
+    node.p2p.reservations.remove(&peer_id);
+    let ip_addr = match node.p2p.connections.remove(&peer_id) {
+        Some(x) => x,
+        None => return,
+    };
+    node.p2p.addresses.remove(&peer_id);
+    node.p2p.diversity.remove(&peer_id);
+    node.p2p.scheduler.remove(&peer_id);
+    if node.headers_peer == Some(peer_id) {
+        node.pending_headers_clear();
+    }
+    node.p2p.statuses.remove(&peer_id);
+    node.p2p.network_time.remove(&peer_id);
+    node.p2p.metrics.remove(&peer_id);
     info!(?ip_addr, num_established, "Connection closed");
 }
 #[instrument(skip_all, level = "trace")]
+fn peer_status(node: &mut Node, peer_id: PeerId, status: Status) {
+    if !node.status().compatible(&status) {
+        warn!(
+            ?peer_id,
+            status.version, status.network, "Disconnecting incompatible peer"
+        );
+        node.p2p.statuses.remove(&peer_id);
+        node.p2p.network_time.remove(&peer_id);
+        let _ = node.p2p.swarm.disconnect_peer_id(peer_id);
+        return;
+    }
+    let offset = status
+        .timestamp_millis()
+        .map(|x| x - node.clock.inner().timestamp_millis());
+    if let Some(offset) = offset {
+        if node.p2p.diversity.direction(&peer_id) == Some(Direction::Outbound) {
+            node.p2p.network_time.insert(peer_id, offset);
+        }
+    }
+    debug!(?peer_id, status.height, ?offset, "Status");
+    node.p2p.statuses.insert(peer_id, status);
+}
+#[instrument(skip_all, level = "trace")]
+fn outbound_failure(
+    node: &mut Node,
+    peer_id: PeerId,
+    request_id: RequestId,
+    error: request_response::OutboundFailure,
+) {
+    node.p2p.metrics.request_failed(&request_id);
+    node.p2p.scheduler.fail(&peer_id);
+    warn!(?error, ?peer_id, "Outbound failure");
+}
+#[instrument(skip_all, level = "trace")]
+fn outgoing_connection_error(node: &mut Node, error: DialError) {
+    if let DialError::Transport(vec) = &error {
+        for (multiaddr, _) in vec {
+            node.candidates.remove(multiaddr);
+            node.book_failure(multiaddr);
+        }
+    }
+    debug!(?error, "Outgoing connection error");
+}
+#[instrument(skip_all, level = "trace")]
 fn mdns(node: &mut Node, event: mdns::Event) {
     match event {
         mdns::Event::Discovered(iter) => {
             for (_, multiaddr) in iter {
-                let ip_addr = multiaddr.ip_addr().unwrap();
-                node.p2p.connections_unknown.insert(ip_addr);
+                node.book_insert(&multiaddr, Source::Mdns);
             }
         }
         mdns::Event::Expired(_) => {}
     }
 }
 #[instrument(skip_all, level = "trace")]
+fn identify(node: &mut Node, peer_id: PeerId, info: identify::Info) {
+    if info.protocols.iter().any(|x| x == PROTOCOL_NAME_KAD) {
+        for multiaddr in info.listen_addrs.iter() {
+            if !node.routable(multiaddr) {
+                continue;
+            }
+            node.p2p
+                .swarm
+                .behaviour_mut()
+                .kademlia
+                .add_address(&peer_id, multiaddr.clone());
+        }
+    }
+    if info.protocols.iter().any(|x| x == PROTOCOL_NAME_RELAY_HOP) {
+        node.p2p.relays.insert(peer_id);
+    }
+    if node.p2p.addresses.contains_key(&peer_id) {
+        relay_listen(node);
+        return;
+    }
+    let ip_addr = match node.p2p.connections.get(&peer_id) {
+        Some(x) => *x,
+        None => return,
+    };
+    let port = match info
+        .listen_addrs
+        .iter()
+        .filter(|x| x.transport() == Some(Transport::Tcp))
+        .find_map(|x| x.port())
+    {
+        Some(x) => x,
+        None => return,
+    };
+    let multiaddr = ip_addr.multiaddr_port(port);
+    debug!(?multiaddr, "Identify");
+    if node.routable(&multiaddr) {
+        node.book_insert(&multiaddr, Source::Identify);
+    }
+    node.p2p.addresses.insert(peer_id, multiaddr);
+    relay_listen(node);
+}
+#[instrument(skip_all, level = "trace")]
+fn relay_listen(node: &mut Node) {
+    if !matches!(
+        node.p2p.swarm.behaviour().autonat.nat_status(),
+        NatStatus::Private
+    ) {
+        return;
+    }
+    if node.p2p.relay_listen() {
+        info!("Listening through relay");
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn autonat(node: &mut Node, event: autonat::Event) {
+    if let autonat::Event::StatusChanged { old, new } = event {
+        info!(?old, ?new, "NAT status");
+        relay_listen(node);
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn relay_client(node: &mut Node, event: relay::client::Event) {
+    match event {
+        relay::client::Event::ReservationReqAccepted {
+            relay_peer_id,
+            renewal,
+            ..
+        } => {
+            if !renewal {
+                info!(?relay_peer_id, "Relay reservation accepted");
+            }
+        }
+        relay::client::Event::ReservationReqFailed {
+            relay_peer_id,
+            error,
+            ..
+        } => {
+            warn!(?relay_peer_id, ?error, "Relay reservation failed");
+            node.p2p.reservations.remove(&relay_peer_id);
+            node.p2p.relays.remove(&relay_peer_id);
+        }
+        event => debug!(?event, "Relay client"),
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn dcutr(event: dcutr::Event) {
+    match event {
+        dcutr::Event::DirectConnectionUpgradeSucceeded { remote_peer_id } => {
+            info!(?remote_peer_id, "Hole punched")
+        }
+        dcutr::Event::DirectConnectionUpgradeFailed {
+            remote_peer_id,
+            error,
+        } => debug!(?remote_peer_id, ?error, "Hole punching failed"),
+        event => debug!(?event, "Dcutr"),
+    }
+}
+#[instrument(skip_all, level = "trace")]
+fn kademlia(node: &mut Node, event: KademliaEvent) {
+    match event {
+        KademliaEvent::RoutingUpdated {
+            peer,
+            is_new_peer,
+            addresses,
+            ..
+        } => {
+            let kademlia = &mut node.p2p.swarm.behaviour_mut().kademlia;
+            if is_new_peer && kademlia.kbuckets().map(|x| x.num_entries()).sum::<usize>() == 1 {
+                if let Err(e) = kademlia.bootstrap() {
+                    debug!(?e);
+                }
+            }
+            for multiaddr in addresses.iter() {
+                if !node.routable(multiaddr) {
+                    continue;
+                }
+                if node.candidate_insert(multiaddr.clone(), Source::Kademlia) {
+                    debug!(?peer, ?multiaddr, "Kademlia");
+                }
+            }
+        }
+        KademliaEvent::OutboundQueryProgressed { result, .. } => match result {
+            QueryResult::Bootstrap(Err(e)) => debug!(?e, "Kademlia bootstrap"),
+            QueryResult::GetClosestPeers(Ok(ok)) => {
+                debug!(len = ok.peers.len(), "Kademlia random walk")
+            }
+            QueryResult::GetClosestPeers(Err(e)) => debug!(?e, "Kademlia random walk"),
+            _ => {}
+        },
+        _ => {}
+    }
+}
+#[instrument(skip_all, level = "trace")]
 fn gossipsub_message(
     node: &mut Node,
     message: gossipsub::Message,
@@ -127,8 +438,18 @@ fn gossipsub_message(
         Blockchain(blockchain::Error),
         MessageSource,
         IpAddr,
+        Topic,
         Ratelimit,
-        SharePeersMaxLen,
+        CompactBlockMissing,
+    }
+    impl Error {
+        fn invalid(&self) -> bool {
+            match self {
+                Error::Bincode(_) => true,
+                Error::Blockchain(e) => e.invalid(),
+                _ => false,
+            }
+        }
     }
     fn inner(
         node: &mut Node,
@@ -140,25 +461,49 @@ fn gossipsub_message(
         if vec_ip_addr.is_empty() {
             return Err(Error::IpAddr);
         }
-        let endpoint = match message.topic.as_str() {
-            "block" => Endpoint::GossipsubMessageBlock,
-            "transaction" => Endpoint::GossipsubMessageTransaction,
-            "stake" => Endpoint::GossipsubMessageStake,
-            "peers" => Endpoint::GossipsubMessagePeers,
-            _ => unreachable!(),
+        let endpoint = match node.p2p.topic(&message.topic).ok_or(Error::Topic)? {
+            Topic::Block => Endpoint::GossipsubMessageBlock,
+            Topic::CompactBlock => Endpoint::GossipsubMessageCompactBlock,
+            Topic::Transaction => Endpoint::GossipsubMessageTransaction,
+            Topic::Stake => Endpoint::GossipsubMessageStake,
         };
         for ip_addr in vec_ip_addr {
-            if node.p2p.ratelimit.counter.add(ip_addr, &endpoint) {
+            if node.p2p.ratelimit.limiter.add(ip_addr, &endpoint) {
                 return Err(Error::Ratelimit);
             }
         }
         match endpoint {
             Endpoint::GossipsubMessageBlock => {
                 let block: Block = bincode::deserialize(&message.data).map_err(Error::Bincode)?;
-                node.blockchain
-                    .pending_blocks_push(&node.db, block, node.args.time_delta, node.args.trust)
-                    .map_err(Error::Blockchain)?;
-                node.blockchain.save_blocks(&node.db, node.args.trust);
+                accept_block(node, propagation_source, block).map_err(Error::Blockchain)?;
+            }
+            Endpoint::GossipsubMessageCompactBlock => {
+                let compact_block: CompactBlock =
+                    bincode::deserialize(&message.data).map_err(Error::Bincode)?;
+                let hash = compact_block.hash();
+                if node.blockchain.pending_block(&hash).is_some() {
+                    return Err(Error::Blockchain(blockchain::Error::BlockPending));
+                }
+                if node.blockchain.tree.get(&hash).is_some() {
+                    return Err(Error::Blockchain(blockchain::Error::BlockHashInTree));
+                }
+                match node.blockchain.compact_blocks_push(
+                    &node.db,
+                    compact_block,
+                    node.args.time_delta,
+                ) {
+                    Ok(block) => {
+                        accept_block(node, propagation_source, block.clone())
+                            .map_err(Error::Blockchain)?;
+                        node.publish_block(&block, false);
+                    }
+                    Err(blockchain::Error::CompactBlockMissing(missing)) => {
+                        node.p2p
+                            .send_request(&propagation_source, Request::BlockItems(hash, missing));
+                        return Err(Error::CompactBlockMissing);
+                    }
+                    Err(e) => return Err(Error::Blockchain(e)),
+                }
             }
             Endpoint::GossipsubMessageTransaction => {
                 let transaction: Transaction =
@@ -173,23 +518,23 @@ fn gossipsub_message(
                     .pending_stakes_push(stake, node.args.time_delta)
                     .map_err(Error::Blockchain)?;
             }
-            Endpoint::GossipsubMessagePeers => {
-                let vec =
-                    bincode::deserialize::<Vec<IpAddr>>(&message.data).map_err(Error::Bincode)?;
-                if vec.len() > SHARE_PEERS_MAX_LEN {
-                    return Err(Error::SharePeersMaxLen);
-                }
-                for ip_addr in vec {
-                    node.p2p.connections_unknown.insert(ip_addr);
-                }
-            }
             _ => unreachable!(),
         }
         Ok(())
     }
+    let ip_addr = node.p2p.connections.get(&propagation_source).cloned();
+    let topic = node.p2p.topic(&message.topic);
+    if topic.is_some() {
+        node.p2p
+            .metrics
+            .inbound(&propagation_source, topic, message.data.len());
+    }
     match match inner(node, &message, propagation_source) {
         Ok(()) => {
             debug!("Gossipsub message processed");
+            if let Some(ip_addr) = ip_addr {
+                node.score(ip_addr, ScoreEvent::Useful);
+            }
             node.p2p
                 .swarm
                 .behaviour_mut()
@@ -200,20 +545,32 @@ fn gossipsub_message(
                     MessageAcceptance::Accept,
                 )
         }
-        Err(Error::Blockchain(blockchain::Error::BlockPending))
-        | Err(Error::Blockchain(blockchain::Error::BlockHashInTree))
-        | Err(Error::Blockchain(blockchain::Error::BlockPreviousHashNotInTree)) => node
-            .p2p
-            .swarm
-            .behaviour_mut()
-            .gossipsub
-            .report_message_validation_result(
-                &message_id,
-                &propagation_source,
-                MessageAcceptance::Ignore,
-            ),
+        Err(e) if !matches!(e, Error::Ratelimit) && !e.invalid() => {
+            debug!(?e);
+            node.p2p
+                .swarm
+                .behaviour_mut()
+                .gossipsub
+                .report_message_validation_result(
+                    &message_id,
+                    &propagation_source,
+                    MessageAcceptance::Ignore,
+                )
+        }
         Err(e) => {
             error!(?e);
+            if topic.is_some() {
+                match e {
+                    Error::Ratelimit => node.p2p.metrics.ratelimited(&propagation_source, topic),
+                    _ => node.p2p.metrics.rejected(&propagation_source, topic),
+                }
+            }
+            if let Some(ip_addr) = ip_addr {
+                match e {
+                    Error::Ratelimit => node.score(ip_addr, ScoreEvent::Ratelimited),
+                    _ => node.score(ip_addr, ScoreEvent::Invalid),
+                }
+            }
             node.p2p
                 .swarm
                 .behaviour_mut()
@@ -243,58 +600,200 @@ fn sync_request(
             return;
         }
     };
-    if node.p2p.ratelimit.counter.add(ip_addr, &Endpoint::Request) {
-        return;
-    }
     #[derive(Debug)]
     enum Error {
         Bincode(bincode::Error),
         Blockchain(blockchain::Error),
-        Response(Response),
+        DB(db::Error),
+        Ratelimit,
+        Timeout,
+        SyncBodiesMaxLen,
     }
-    fn inner(
-        node: &mut Node,
-        request: Request,
-        channel: ResponseChannel<Response>,
-    ) -> Result<(), Error> {
-        let height: usize = bincode::deserialize(&request.0).map_err(Error::Bincode)?;
+    fn range(node: &mut Node, start: usize, end: usize) -> Result<Vec<Block>, Error> {
         let mut size = 0;
         let mut vec = vec![];
-        loop {
-            let index = height + vec.len();
+        for index in start..end {
             let res = node.blockchain.sync_block(&node.db, index);
             if let Err(blockchain::Error::SyncBlock) = res {
                 break;
             }
             let block = res.map_err(Error::Blockchain)?;
             size += bincode::serialize(&block).map_err(Error::Bincode)?.len();
-            if size > MAX_TRANSMIT_SIZE {
+            if size > MAX_RESPONSE_SIZE {
                 break;
             }
             vec.push(block);
         }
-        let vec = bincode::serialize(&vec).map_err(Error::Bincode)?;
-        node.p2p
-            .swarm
-            .behaviour_mut()
-            .request_response
-            .send_response(channel, Response(vec))
-            .map_err(Error::Response)?;
-        Ok(())
+        Ok(vec)
     }
-    match inner(node, request, channel) {
-        Ok(()) => debug!("Sync request processed"),
-        Err(e) => {
-            error!(?e);
+    fn headers(node: &mut Node, height: usize) -> Result<Vec<Header>, Error> {
+        let mut size = 0;
+        let mut vec = vec![];
+        loop {
+            let index = height + vec.len();
+            let res = node.blockchain.sync_header(&node.db, index);
+            if let Err(blockchain::Error::SyncBlock) = res {
+                break;
+            }
+            let header = res.map_err(Error::Blockchain)?;
+            size += bincode::serialize(&header).map_err(Error::Bincode)?.len();
+            if size > MAX_RESPONSE_SIZE {
+                break;
+            }
+            vec.push(header);
+        }
+        Ok(vec)
+    }
+    fn bodies(node: &mut Node, hashes: Vec<[u8; 32]>) -> Result<Vec<Block>, Error> {
+        if hashes.len() > SYNC_BODIES_MAX_LEN {
+            return Err(Error::SyncBodiesMaxLen);
+        }
+        let mut size = 0;
+        let mut vec = vec![];
+        for hash in hashes {
+            let block = match db::block::get(&node.db, &hash) {
+                Ok(x) => x,
+                Err(db::Error::NotFound) => break,
+                Err(e) => return Err(Error::DB(e)),
+            };
+            size += bincode::serialize(&block).map_err(Error::Bincode)?.len();
+            if size > MAX_RESPONSE_SIZE {
+                break;
+            }
+            vec.push(block);
+        }
+        Ok(vec)
+    }
+    fn pending(node: &mut Node) -> Result<Response, Error> {
+        let mut size = 0;
+        let mut transactions = vec![];
+        let mut stakes = vec![];
+        for transaction in node.blockchain.pending_transactions() {
+            size += bincode::serialize(transaction)
+                .map_err(Error::Bincode)?
+                .len();
+            if size > MAX_RESPONSE_SIZE {
+                return Ok(Response::Pending(transactions, stakes));
+            }
+            transactions.push(*transaction);
+        }
+        for stake in node.blockchain.pending_stakes() {
+            size += bincode::serialize(stake).map_err(Error::Bincode)?.len();
+            if size > MAX_RESPONSE_SIZE {
+                break;
+            }
+            stakes.push(*stake);
+        }
+        Ok(Response::Pending(transactions, stakes))
+    }
+    fn peers(node: &mut Node) -> Result<Vec<PeerRecord>, Error> {
+        let timestamp = node.clock.timestamp();
+        let mut vec: Vec<PeerRecord> = db::peer::get_all(&node.db)
+            .map_err(Error::DB)?
+            .into_iter()
+            .filter(|(address, peer)| {
+                peer.last_success > peer.last_failure
+                    && timestamp.saturating_sub(peer.last_success) <= PEER_FORGET_AFTER
+                    && address
+                        .parse::<Multiaddr>()
+                        .map(|x| node.routable(&x))
+                        .unwrap_or_default()
+            })
+            .map(|(address, peer)| PeerRecord {
+                address,
+                timestamp: peer.last_success,
+            })
+            .collect();
+        vec.shuffle(&mut thread_rng());
+        vec.truncate(SHARE_PEERS_MAX_LEN);
+        Ok(vec)
+    }
+    fn block_items(node: &mut Node, hash: [u8; 32], missing: Missing) -> Result<Response, Error> {
+        let block = match node.blockchain.pending_block(&hash) {
+            Some(block) => block.clone(),
+            None => db::block::get(&node.db, &hash).map_err(Error::DB)?,
+        };
+        let transactions = missing
+            .transactions
+            .iter()
+            .filter_map(|i| block.transactions.get(*i as usize).cloned())
+            .collect();
+        let stakes = missing
+            .stakes
+            .iter()
+            .filter_map(|i| block.stakes.get(*i as usize).cloned())
+            .collect();
+        Ok(Response::BlockItems(hash, transactions, stakes))
+    }
+    fn inner(
+        node: &mut Node,
+        peer_id: PeerId,
+        ip_addr: IpAddr,
+        request: Request,
+    ) -> Result<Response, Error> {
+        if node.p2p.ratelimit.timeout.has(ip_addr, Endpoint::Request) {
+            return Err(Error::Timeout);
+        }
+        if node.p2p.ratelimit.limiter.add(ip_addr, &Endpoint::Request) {
+            return Err(Error::Ratelimit);
+        }
+        let response = match request {
+            Request::Blocks(height) => Response::Blocks(range(node, height, usize::MAX)?),
+            Request::Headers(height) => Response::Headers(headers(node, height)?),
+            Request::Bodies(hashes) => Response::Blocks(bodies(node, hashes)?),
+            Request::Range(start, end) => Response::Blocks(range(node, start, end)?),
+            Request::Block(hash) => Response::Block(db::block::get(&node.db, &hash).ok()),
+            Request::Pending => pending(node)?,
+            Request::Peers => Response::Peers(peers(node)?),
+            Request::BlockItems(hash, missing) => block_items(node, hash, missing)?,
+            Request::Status(status) => {
+                peer_status(node, peer_id, status);
+                Response::Status(node.status())
+            }
+        };
+        Ok(response)
+    }
+    let bytes = bincode::serialized_size(&request).unwrap_or_default() as usize;
+    node.p2p.metrics.inbound(&peer_id, None, bytes);
+    let response = match inner(node, peer_id, ip_addr, request) {
+        Ok(response) => {
+            debug!("Sync request processed");
+            response
+        }
+        Err(Error::Ratelimit) => {
+            node.p2p.metrics.ratelimited(&peer_id, None);
+            node.score(ip_addr, ScoreEvent::Ratelimited);
+            Response::Refused(Refusal::Ratelimited)
+        }
+        Err(Error::Timeout) => {
+            node.p2p.metrics.ratelimited(&peer_id, None);
+            Response::Refused(Refusal::Ratelimited)
+        }
+        Err(Error::SyncBodiesMaxLen) => {
+            node.p2p.metrics.rejected(&peer_id, None);
+            node.score(ip_addr, ScoreEvent::Invalid);
             node.p2p
                 .ratelimit
                 .timeout
                 .insert(ip_addr, Endpoint::Request);
+            Response::Refused(Refusal::TooLarge)
         }
+        Err(e) => {
+            error!(?e);
+            Response::Refused(Refusal::Unavailable)
+        }
+    };
+    if let Err(e) = node.p2p.send_response(&peer_id, channel, response) {
+        error!(?e, "Failed to send response");
     }
 }
 #[instrument(skip_all, level = "trace")]
-fn sync_response(node: &mut Node, peer_id: PeerId, response: Response) {
+fn sync_response(node: &mut Node, peer_id: PeerId, request_id: RequestId, response: Response) {
+    node.p2p
+        .metrics
+        .response_received(&request_id, node.clock.timestamp_millis() as u64);
+    let bytes = bincode::serialized_size(&response).unwrap_or_default() as usize;
+    node.p2p.metrics.inbound(&peer_id, None, bytes);
     let ip_addr = match node.p2p.connections.get(&peer_id) {
         Some(x) => *x,
         None => {
@@ -302,25 +801,175 @@ fn sync_response(node: &mut Node, peer_id: PeerId, response: Response) {
             return;
         }
     };
-    if node.p2p.ratelimit.counter.add(ip_addr, &Endpoint::Response) {
+    if node.p2p.ratelimit.limiter.add(ip_addr, &Endpoint::Response) {
+        node.p2p.scheduler.fail(&peer_id);
+        node.p2p.metrics.ratelimited(&peer_id, None);
+        node.score(ip_addr, ScoreEvent::Ratelimited);
         return;
     }
     #[derive(Debug)]
     enum Error {
-        Bincode(bincode::Error),
         Blockchain(blockchain::Error),
+        Refused(Refusal),
+        SharePeersMaxLen,
+        CompactBlock,
     }
-    fn inner(node: &mut Node, response: Response) -> Result<(), Error> {
-        for block in bincode::deserialize::<Vec<Block>>(&response.0).map_err(Error::Bincode)? {
-            node.blockchain
-                .pending_blocks_push(&node.db, block, node.args.time_delta, node.args.trust)
-                .map_err(Error::Blockchain)?;
-            node.blockchain.save_blocks(&node.db, node.args.trust);
+    impl Error {
+        fn invalid(&self) -> bool {
+            match self {
+                Error::Blockchain(e) => e.invalid(),
+                Error::SharePeersMaxLen => true,
+                _ => false,
+            }
+        }
+    }
+    fn inner(node: &mut Node, peer_id: PeerId, response: Response) -> Result<(), Error> {
+        match response {
+            Response::Blocks(blocks) => {
+                if node.blockchain.pending_headers_is_empty() {
+                    for block in blocks {
+                        node.blockchain
+                            .pending_blocks_push(
+                                &node.db,
+                                block,
+                                node.args.time_delta,
+                                node.args.trust,
+                            )
+                            .map_err(Error::Blockchain)?;
+                        node.blockchain.save_blocks(&node.db, node.args.trust);
+                    }
+                    return Ok(());
+                }
+                if blocks.is_empty() {
+                    node.p2p.scheduler.fail(&peer_id);
+                    return Err(Error::Refused(Refusal::Unavailable));
+                }
+                for block in blocks {
+                    if let Err(e) = node.blockchain.pending_bodies_push(block) {
+                        node.p2p.scheduler.fail(&peer_id);
+                        return Err(Error::Blockchain(e));
+                    }
+                }
+                node.p2p.scheduler.complete(&peer_id);
+                for block in node.blockchain.pending_bodies_take() {
+                    if let Err(e) = node.blockchain.pending_blocks_push(
+                        &node.db,
+                        block,
+                        node.args.time_delta,
+                        node.args.trust,
+                    ) {
+                        node.pending_headers_clear();
+                        return Err(Error::Blockchain(e));
+                    }
+                    node.blockchain.save_blocks(&node.db, node.args.trust);
+                }
+            }
+            Response::Headers(headers) => {
+                node.blockchain
+                    .pending_headers_push(&node.db, headers, node.args.time_delta)
+                    .map_err(Error::Blockchain)?;
+                if !node.blockchain.pending_headers_is_empty() {
+                    node.headers_peer = Some(peer_id);
+                }
+            }
+            Response::Block(Some(block)) => {
+                accept_block(node, peer_id, block).map_err(Error::Blockchain)?;
+            }
+            Response::Block(None) => {}
+            Response::Pending(transactions, stakes) => {
+                for transaction in transactions {
+                    let _ = node
+                        .blockchain
+                        .pending_transactions_push(transaction, node.args.time_delta);
+                }
+                for stake in stakes {
+                    let _ = node
+                        .blockchain
+                        .pending_stakes_push(stake, node.args.time_delta);
+                }
+            }
+            Response::Peers(vec) => {
+                if vec.len() > SHARE_PEERS_MAX_LEN {
+                    return Err(Error::SharePeersMaxLen);
+                }
+                let timestamp = node.clock.timestamp();
+                let mut vec: Vec<PeerRecord> = vec
+                    .into_iter()
+                    .filter(|x| {
+                        x.timestamp <= timestamp + node.args.time_delta
+                            && timestamp.saturating_sub(x.timestamp) <= PEER_FORGET_AFTER
+                    })
+                    .collect();
+                vec.shuffle(&mut thread_rng());
+                vec.truncate(SHARE_PEERS_SAMPLE_LEN);
+                for peer in vec {
+                    match multiaddr::parse(&peer.address, node.args.testnet) {
+                        Some(multiaddr) if node.routable(&multiaddr) => {
+                            node.candidate_insert(multiaddr, Source::Exchange);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Response::Status(status) => peer_status(node, peer_id, status),
+            Response::BlockItems(hash, transactions, stakes) => {
+                let block = node
+                    .blockchain
+                    .compact_blocks_fill(&hash, transactions, stakes)
+                    .ok_or(Error::CompactBlock)?;
+                accept_block(node, peer_id, block.clone()).map_err(Error::Blockchain)?;
+                node.publish_block(&block, true);
+            }
+            Response::Refused(refusal) => {
+                node.p2p.scheduler.fail(&peer_id);
+                return Err(Error::Refused(refusal));
+            }
         }
         Ok(())
     }
-    match inner(node, response) {
-        Ok(()) => debug!("Sync response processed"),
-        Err(e) => error!(?e, ?peer_id),
+    let res = inner(node, peer_id, response);
+    node.blockchain
+        .orphans_connect(&node.db, node.args.time_delta, node.args.trust);
+    match res {
+        Ok(()) => {
+            debug!("Sync response processed");
+            node.score(ip_addr, ScoreEvent::Useful);
+        }
+        Err(Error::Refused(refusal)) => debug!(?refusal, ?peer_id),
+        Err(e) if e.invalid() => {
+            error!(?e, ?peer_id);
+            node.score(ip_addr, ScoreEvent::Invalid);
+        }
+        Err(e) => debug!(?e, ?peer_id),
     }
 }
+fn accept_block(node: &mut Node, peer_id: PeerId, block: Block) -> Result<(), blockchain::Error> {
+    let previous_hash = block.previous_hash;
+    if let Err(e) = node.blockchain.pending_blocks_push(
+        &node.db,
+        block.clone(),
+        node.args.time_delta,
+        node.args.trust,
+    ) {
+        if !matches!(e, blockchain::Error::BlockPreviousHashNotInTree) {
+            return Err(e);
+        }
+        let hash = block.hash();
+        if node.blockchain.orphans_push(block, node.args.time_delta)? {
+            if let Some(ip_addr) = node.p2p.connections.get(&peer_id) {
+                node.orphans.insert(hash, *ip_addr);
+            }
+            request_block(node, peer_id, previous_hash);
+        }
+        node.orphans_prune();
+        return Err(e);
+    }
+    node.blockchain.save_blocks(&node.db, node.args.trust);
+    node.blockchain
+        .orphans_connect(&node.db, node.args.time_delta, node.args.trust);
+    node.orphans_prune();
+    Ok(())
+}
+fn request_block(node: &mut Node, peer_id: PeerId, hash: [u8; 32]) {
+    node.p2p.send_request(&peer_id, Request::Block(hash));
+}